@@ -0,0 +1,623 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+
+use rust_stemmers::Stemmer;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+use crate::parse::{Occurrence, TokenProcessor, TokenSlice, parse_text};
+
+// A token on its way through an analyzer. The text may have been changed by filters, but
+// the occurrence always describes where the original text is in the document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
+    pub text: Cow<'a, str>,
+    pub occurrence: Occurrence,
+}
+
+// Splits text into tokens.
+pub trait Tokenizer: Debug + Send + Sync {
+    fn tokenize<'a>(&self, text: &'a str, output: &mut dyn FnMut(Token<'a>));
+
+    // A name and version for the tokenizer, along with any settings, like "parse-v1". An
+    // index saves it to tell if it's opened with a different tokenizer, so the version has
+    // to change whenever the tokens do.
+    fn id(&self) -> String;
+}
+
+// Changes, removes, or adds tokens. Each token passed to filter should result in zero or more
+// calls to output.
+pub trait TokenFilter: Debug + Send + Sync {
+    fn filter<'a>(&self, token: Token<'a>, output: &mut dyn FnMut(Token<'a>));
+
+    // Like Tokenizer::id, filters that make different tokens need different ids.
+    fn id(&self) -> String;
+}
+
+// The tokenizer used by parse_text, which emits each word along with its camelCase and
+// alphanumeric pieces.
+#[derive(Debug, Default, Clone)]
+pub struct ParseTokenizer {}
+
+// Turns the tokens from parse_text into Tokens.
+struct ParseAdapter<'a, 'o> {
+    text: &'a str,
+    output: &'o mut dyn FnMut(Token<'a>),
+    word_offset: usize,
+    word_column: usize,
+}
+
+impl TokenProcessor for ParseAdapter<'_, '_> {
+    fn process_token(&mut self, token: &TokenSlice) {
+        let mut occurrence = token.occurrence;
+        // parse_text gives pieces of a word the column of the whole word.
+        if occurrence.partial {
+            let before = &self.text[self.word_offset..occurrence.offset];
+            occurrence.column = self.word_column + before.chars().count();
+        } else {
+            self.word_offset = occurrence.offset;
+            self.word_column = occurrence.column;
+        }
+        let start = occurrence.offset;
+        (self.output)(Token {
+            text: Cow::Borrowed(&self.text[start..start + occurrence.length]),
+            occurrence,
+        });
+    }
+}
+
+impl Tokenizer for ParseTokenizer {
+    fn tokenize<'a>(&self, text: &'a str, output: &mut dyn FnMut(Token<'a>)) {
+        let mut adapter = ParseAdapter {
+            text,
+            output,
+            word_offset: 0,
+            word_column: 0,
+        };
+        parse_text(text, &mut adapter);
+    }
+
+    fn id(&self) -> String {
+        "parse-v1".to_string()
+    }
+}
+
+// Turns text into the terms that are stored in an index, or looked up in a search.
+// The same analyzer has to be used for both, or searches won't find what was indexed.
+#[derive(Debug)]
+pub struct Analyzer {
+    tokenizer: Box<dyn Tokenizer>,
+    filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl Default for Analyzer {
+    // Indexes every token exactly as it appears in the text.
+    fn default() -> Self {
+        Analyzer::new(ParseTokenizer {})
+    }
+}
+
+impl Analyzer {
+    pub fn new(tokenizer: impl Tokenizer + 'static) -> Self {
+        Analyzer {
+            tokenizer: Box::new(tokenizer),
+            filters: Vec::new(),
+        }
+    }
+
+    // Ignores case and compatibility forms, like "ﬁ" for "fi". The command line tools use
+    // this one.
+    pub fn normalized() -> Self {
+        Analyzer::default().with_filter(NormalizeFilter::default())
+    }
+
+    // Adds a filter, which is applied after the ones already added.
+    pub fn with_filter(mut self, filter: impl TokenFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    // Passes each token of the text through the filters, in order.
+    pub fn tokens<'a>(&self, text: &'a str, output: &mut dyn FnMut(Token<'a>)) {
+        self.tokenizer.tokenize(text, &mut |token| {
+            apply_filters(&self.filters, token, output)
+        });
+    }
+
+    // Describes the tokenizer and every filter, in order. An index saves the id of the
+    // analyzer it was built with, since terms from any other analyzer wouldn't match.
+    pub fn id(&self) -> String {
+        let mut id = self.tokenizer.id();
+        for filter in self.filters.iter() {
+            id.push_str(" | ");
+            id.push_str(&filter.id());
+        }
+        id
+    }
+
+    // Sends each analyzed token of the text to the processor.
+    pub fn analyze(&self, text: &str, processor: &mut impl TokenProcessor) {
+        self.tokens(text, &mut |token| {
+            processor.process_token(&TokenSlice {
+                token: &token.text,
+                occurrence: token.occurrence,
+            })
+        });
+    }
+}
+
+fn apply_filters<'a>(
+    filters: &[Box<dyn TokenFilter>],
+    token: Token<'a>,
+    output: &mut dyn FnMut(Token<'a>),
+) {
+    match filters.split_first() {
+        Some((filter, rest)) => {
+            filter.filter(token, &mut |token| apply_filters(rest, token, output));
+        }
+        None => output(token),
+    }
+}
+
+// Lowercases every token.
+#[derive(Debug, Default, Clone)]
+pub struct LowercaseFilter {}
+
+impl TokenFilter for LowercaseFilter {
+    fn filter<'a>(&self, mut token: Token<'a>, output: &mut dyn FnMut(Token<'a>)) {
+        if token.text.chars().any(char::is_uppercase) {
+            token.text = Cow::Owned(token.text.to_lowercase());
+        }
+        output(token);
+    }
+
+    fn id(&self) -> String {
+        "lowercase-v1".to_string()
+    }
+}
+
+// Makes text that reads the same into the same term. NFKC normalization turns compatibility
+// characters like "ﬁ" or "２" into their plain forms, and case folding makes "Straße",
+// "STRASSE" and "strasse" one term. Accents can also be removed, so that "café" matches "cafe".
+#[derive(Debug, Default, Clone)]
+pub struct NormalizeFilter {
+    pub strip_accents: bool,
+}
+
+impl TokenFilter for NormalizeFilter {
+    fn filter<'a>(&self, mut token: Token<'a>, output: &mut dyn FnMut(Token<'a>)) {
+        if !token.text.is_ascii() {
+            token.text = Cow::Owned(normalize(&token.text, self.strip_accents));
+        } else if token.text.bytes().any(|b| b.is_ascii_uppercase()) {
+            token.text = Cow::Owned(token.text.to_ascii_lowercase());
+        }
+        // A token that was only accents has nothing left to index.
+        if !token.text.is_empty() {
+            output(token);
+        }
+    }
+
+    fn id(&self) -> String {
+        if self.strip_accents {
+            "normalize-v1:strip-accents".to_string()
+        } else {
+            "normalize-v1".to_string()
+        }
+    }
+}
+
+fn normalize(text: &str, strip_accents: bool) -> String {
+    // Folding can undo the normalization, so it's normalized again afterwards.
+    let folded = caseless::default_case_fold_str(&text.nfkc().collect::<String>());
+    if strip_accents {
+        folded
+            .nfd()
+            .filter(|c| !is_combining_mark(*c))
+            .nfkc()
+            .collect()
+    } else {
+        folded.nfkc().collect()
+    }
+}
+
+// The languages that words can be stemmed in.
+pub use rust_stemmers::Algorithm as Language;
+
+// The name of a language in an analyzer id.
+fn language_name(language: Language) -> &'static str {
+    match language {
+        Language::Arabic => "arabic",
+        Language::Danish => "danish",
+        Language::Dutch => "dutch",
+        Language::English => "english",
+        Language::Finnish => "finnish",
+        Language::French => "french",
+        Language::German => "german",
+        Language::Greek => "greek",
+        Language::Hungarian => "hungarian",
+        Language::Italian => "italian",
+        Language::Norwegian => "norwegian",
+        Language::Portuguese => "portuguese",
+        Language::Romanian => "romanian",
+        Language::Russian => "russian",
+        Language::Spanish => "spanish",
+        Language::Swedish => "swedish",
+        Language::Tamil => "tamil",
+        Language::Turkish => "turkish",
+    }
+}
+
+// Put in front of the exact form of a word, when a StemFilter keeps both.
+pub const EXACT_MARKER: char = '=';
+
+// Reduces each word to its stem, so that "indexing", "indexed" and "indexes" are one term.
+// The stemmers expect lowercase text, so this should come after a NormalizeFilter.
+pub struct StemFilter {
+    language: Language,
+    keep_exact: bool,
+    stemmer: Stemmer,
+}
+
+impl Debug for StemFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StemFilter")
+            .field("language", &self.language)
+            .field("keep_exact", &self.keep_exact)
+            .finish()
+    }
+}
+
+impl Clone for StemFilter {
+    fn clone(&self) -> Self {
+        StemFilter {
+            language: self.language,
+            keep_exact: self.keep_exact,
+            stemmer: Stemmer::create(self.language),
+        }
+    }
+}
+
+impl StemFilter {
+    pub fn new(language: Language) -> Self {
+        StemFilter {
+            language,
+            keep_exact: false,
+            stemmer: Stemmer::create(language),
+        }
+    }
+
+    // Also indexes each word as it was written, with EXACT_MARKER in front of it, so that a
+    // search for "=indexing" only finds "indexing".
+    pub fn with_exact(mut self) -> Self {
+        self.keep_exact = true;
+        self
+    }
+}
+
+impl TokenFilter for StemFilter {
+    fn filter<'a>(&self, mut token: Token<'a>, output: &mut dyn FnMut(Token<'a>)) {
+        let exact = self
+            .keep_exact
+            .then(|| format!("{EXACT_MARKER}{}", token.text));
+        let stem = self.stemmer.stem(&token.text);
+        if stem != token.text {
+            token.text = Cow::Owned(stem.into_owned());
+        }
+        let occurrence = token.occurrence;
+        output(token);
+        if let Some(exact) = exact {
+            output(Token {
+                text: Cow::Owned(exact),
+                occurrence,
+            });
+        }
+    }
+
+    fn id(&self) -> String {
+        let language = language_name(self.language);
+        if self.keep_exact {
+            format!("stem-v1:{language}:exact")
+        } else {
+            format!("stem-v1:{language}")
+        }
+    }
+}
+
+// Removes common words that aren't worth indexing.
+#[derive(Debug, Clone)]
+pub struct StopwordFilter {
+    words: HashSet<String>,
+}
+
+// A short list of English words that are too common to be useful in a search.
+const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+impl StopwordFilter {
+    pub fn new<S: Into<String>>(words: impl IntoIterator<Item = S>) -> Self {
+        StopwordFilter {
+            words: words.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    // Stopwords are matched exactly, so this should come after a LowercaseFilter.
+    pub fn english() -> Self {
+        Self::new(ENGLISH_STOPWORDS.iter().copied())
+    }
+}
+
+impl TokenFilter for StopwordFilter {
+    fn filter<'a>(&self, token: Token<'a>, output: &mut dyn FnMut(Token<'a>)) {
+        if !self.words.contains(token.text.as_ref()) {
+            output(token);
+        }
+    }
+
+    // The words are sorted, since a HashSet's order changes from run to run.
+    fn id(&self) -> String {
+        let mut words: Vec<&str> = self.words.iter().map(String::as_str).collect();
+        words.sort();
+        format!("stopwords-v1:{}", words.join(","))
+    }
+}
+
+// Adds the synonyms of each token at the same place in the document, so that a search for
+// any of them finds the others.
+#[derive(Debug, Clone, Default)]
+pub struct SynonymFilter {
+    synonyms: HashMap<String, Vec<String>>,
+}
+
+impl SynonymFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Makes every word in the group a synonym of every other.
+    pub fn with_group<S: Into<String>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+        let words: Vec<String> = words.into_iter().map(Into::into).collect();
+        for word in words.iter() {
+            let synonyms = self.synonyms.entry(word.clone()).or_default();
+            for other in words.iter() {
+                if other != word && !synonyms.contains(other) {
+                    synonyms.push(other.clone());
+                }
+            }
+        }
+        self
+    }
+}
+
+impl TokenFilter for SynonymFilter {
+    fn filter<'a>(&self, token: Token<'a>, output: &mut dyn FnMut(Token<'a>)) {
+        let occurrence = token.occurrence;
+        let synonyms = self.synonyms.get(token.text.as_ref());
+        output(token);
+        for synonym in synonyms.into_iter().flatten() {
+            output(Token {
+                text: Cow::Owned(synonym.clone()),
+                occurrence,
+            });
+        }
+    }
+
+    // Synonyms are added at the same place, so their order doesn't change the tokens.
+    fn id(&self) -> String {
+        let mut groups: Vec<String> = self
+            .synonyms
+            .iter()
+            .map(|(word, synonyms)| {
+                let mut synonyms: Vec<&str> = synonyms.iter().map(String::as_str).collect();
+                synonyms.sort();
+                format!("{word}={}", synonyms.join(","))
+            })
+            .collect();
+        groups.sort();
+        format!("synonyms-v1:{}", groups.join(";"))
+    }
+}
+
+// Removes tokens with fewer than min or more than max characters.
+#[derive(Debug, Clone)]
+pub struct LengthFilter {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl TokenFilter for LengthFilter {
+    fn filter<'a>(&self, token: Token<'a>, output: &mut dyn FnMut(Token<'a>)) {
+        let length = token.text.chars().count();
+        if length >= self.min && length <= self.max {
+            output(token);
+        }
+    }
+
+    fn id(&self) -> String {
+        format!("length-v1:{}-{}", self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the text of each token, and whether it's part of a longer word.
+    fn analyze(analyzer: &Analyzer, text: &str) -> Vec<(String, bool)> {
+        let mut tokens = Vec::new();
+        analyzer.tokens(text, &mut |token| {
+            tokens.push((token.text.into_owned(), token.occurrence.partial))
+        });
+        tokens
+    }
+
+    fn words(tokens: &[(&str, bool)]) -> Vec<(String, bool)> {
+        tokens
+            .iter()
+            .map(|(text, partial)| (text.to_string(), *partial))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_analyzer() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            words(&[
+                ("The", false),
+                ("fooBar", false),
+                ("foo", true),
+                ("Bar", true)
+            ]),
+            analyze(&analyzer, "The fooBar")
+        );
+    }
+
+    #[test]
+    fn test_occurrences() {
+        let mut tokens = Vec::new();
+        Analyzer::default().tokens("x\n  naïveParse", &mut |token| tokens.push(token));
+
+        assert_eq!(4, tokens.len());
+        let parse = &tokens[3];
+        assert_eq!("Parse", parse.text);
+        assert_eq!(1, parse.occurrence.line);
+        assert_eq!(7, parse.occurrence.column);
+        assert_eq!(10, parse.occurrence.offset);
+        assert_eq!(5, parse.occurrence.length);
+    }
+
+    #[test]
+    fn test_normalize_filter() {
+        let analyzer = Analyzer::default().with_filter(NormalizeFilter::default());
+        assert_eq!(
+            words(&[
+                ("über", false),
+                ("strasse", false),
+                ("strasse", false),
+                ("file", false),
+                ("café", false),
+                ("a", false),
+            ]),
+            analyze(&analyzer, "ÜBER straße STRASSE ﬁle Café 𝐀")
+        );
+
+        let analyzer = Analyzer::default().with_filter(NormalizeFilter {
+            strip_accents: true,
+        });
+        assert_eq!(
+            words(&[("uber", false), ("cafe", false), ("cafe", false)]),
+            analyze(&analyzer, "Über café CAFÉ")
+        );
+
+        // The occurrence still points at the text as it was written.
+        let mut tokens = Vec::new();
+        analyzer.tokens("ÜBER 𝐀Ü", &mut |token| tokens.push(token));
+        assert_eq!(2, tokens.len());
+        assert_eq!("au", tokens[1].text);
+        assert_eq!(6, tokens[1].occurrence.offset);
+        assert_eq!(6, tokens[1].occurrence.length);
+        assert_eq!(5, tokens[1].occurrence.column);
+    }
+
+    #[test]
+    fn test_stem_filter() {
+        let analyzer = Analyzer::default()
+            .with_filter(NormalizeFilter::default())
+            .with_filter(StemFilter::new(Language::English));
+        assert_eq!(
+            words(&[
+                ("index", false),
+                ("index", false),
+                ("index", false),
+                ("runindex", false),
+                ("run", true),
+                ("index", true),
+            ]),
+            analyze(&analyzer, "indexing Indexed indexes runIndexes")
+        );
+
+        let analyzer =
+            Analyzer::default().with_filter(StemFilter::new(Language::German).with_exact());
+        assert_eq!(
+            words(&[("haus", false), ("=häuser", false)]),
+            analyze(&analyzer, "häuser")
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let analyzer = Analyzer::default()
+            .with_filter(LowercaseFilter {})
+            .with_filter(StopwordFilter::english())
+            .with_filter(LengthFilter { min: 2, max: 8 })
+            .with_filter(SynonymFilter::new().with_group(["car", "automobile"]));
+
+        assert_eq!(
+            words(&[
+                ("car", false),
+                ("automobile", false),
+                ("xmlhttp", false),
+                ("xml", true),
+                ("http", true),
+            ]),
+            analyze(&analyzer, "The Car is a b XMLHttp averyverylongword")
+        );
+
+        // The occurrence of a changed token still points at the original text.
+        let mut tokens = Vec::new();
+        analyzer.tokens("A CAR", &mut |token| tokens.push(token));
+        assert_eq!("automobile", tokens[1].text);
+        assert_eq!(2, tokens[1].occurrence.offset);
+        assert_eq!(3, tokens[1].occurrence.length);
+    }
+
+    #[test]
+    fn test_analyzer_id() {
+        let analyzer = || {
+            Analyzer::default()
+                .with_filter(NormalizeFilter::default())
+                .with_filter(StopwordFilter::english())
+                .with_filter(SynonymFilter::new().with_group(["car", "auto", "automobile"]))
+        };
+        assert!(
+            analyzer()
+                .id()
+                .starts_with("parse-v1 | normalize-v1 | stopwords-v1:a,an,and,")
+        );
+        assert!(analyzer().id().ends_with(
+            " | synonyms-v1:auto=automobile,car;automobile=auto,car;car=auto,automobile"
+        ));
+        assert_eq!(analyzer().id(), analyzer().id());
+
+        // Different settings or a different order of filters make a different analyzer.
+        assert_eq!("parse-v1", Analyzer::default().id());
+        assert_eq!(
+            "parse-v1 | normalize-v1:strip-accents | stem-v1:english:exact | length-v1:2-8",
+            Analyzer::default()
+                .with_filter(NormalizeFilter {
+                    strip_accents: true
+                })
+                .with_filter(StemFilter::new(Language::English).with_exact())
+                .with_filter(LengthFilter { min: 2, max: 8 })
+                .id()
+        );
+        assert_ne!(
+            analyzer().id(),
+            analyzer().with_filter(LengthFilter { min: 2, max: 8 }).id()
+        );
+        let stem = |filter: StemFilter| Analyzer::default().with_filter(filter).id();
+        assert_ne!(
+            stem(StemFilter::new(Language::English)),
+            stem(StemFilter::new(Language::English).with_exact())
+        );
+        let lower_then_stop = Analyzer::default()
+            .with_filter(LowercaseFilter {})
+            .with_filter(StopwordFilter::english());
+        let stop_then_lower = Analyzer::default()
+            .with_filter(StopwordFilter::english())
+            .with_filter(LowercaseFilter {});
+        assert_ne!(lower_then_stop.id(), stop_then_lower.id());
+    }
+}
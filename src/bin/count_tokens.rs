@@ -1,15 +1,184 @@
-use tangerine::parse::{TokenProcessor, TokenSlice, parse_text};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
-struct TokenPrinter {}
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde_json::{Value, json};
+use tangerine::analyzer::Analyzer;
+use tangerine::parse::{TokenProcessor, TokenSlice};
 
-impl TokenProcessor for TokenPrinter {
+/// Prints the terms the tangerine command would index for some text, to help debug why
+/// a word does or doesn't match.
+#[derive(Parser)]
+struct Args {
+    /// The files to tokenize. With no files, or with -, reads from stdin.
+    files: Vec<PathBuf>,
+
+    /// Print how many times each token occurs instead of every occurrence.
+    #[arg(short, long)]
+    counts: bool,
+
+    /// Print the output as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+// One token, with everything the parser said about it.
+struct Token {
+    token: String,
+    position: usize,
+    offset: usize,
+    length: usize,
+    line: usize,
+    column: usize,
+    partial: bool,
+}
+
+struct TokenCollector {
+    tokens: Vec<Token>,
+}
+
+impl TokenProcessor for TokenCollector {
     fn process_token(&mut self, token: &TokenSlice) {
-        let word = token.token;
-        println!("{word}")
+        self.tokens.push(Token {
+            token: token.token.to_string(),
+            position: token.occurrence.position,
+            offset: token.occurrence.offset,
+            length: token.occurrence.length,
+            line: token.occurrence.line,
+            column: token.occurrence.column,
+            partial: token.occurrence.partial,
+        });
+    }
+}
+
+// How often a token occurs, as a whole word and as part of a longer word.
+#[derive(Default)]
+struct TokenCount {
+    whole: usize,
+    partial: usize,
+}
+
+// Returns the count for each distinct token, most frequent first.
+fn count(tokens: &[Token]) -> Vec<(&str, TokenCount)> {
+    let mut counts: HashMap<&str, TokenCount> = HashMap::new();
+    for token in tokens {
+        let count = counts.entry(&token.token).or_default();
+        if token.partial {
+            count.partial += 1;
+        } else {
+            count.whole += 1;
+        }
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| {
+        let a_total = a_count.whole + a_count.partial;
+        let b_total = b_count.whole + b_count.partial;
+        b_total.cmp(&a_total).then(a.cmp(b))
+    });
+    counts
+}
+
+// Invalid UTF-8 is replaced, the same way the index does it.
+fn read_input(file: &Path) -> Result<String> {
+    let bytes = if file.as_os_str() == "-" {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .context("unable to read stdin")?;
+        bytes
+    } else {
+        fs::read(file).with_context(|| format!("unable to read {}", file.display()))?
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn print_text(name: &str, tokens: &[Token], counts: bool, header: bool) {
+    if header {
+        println!("==> {name} <==");
+    }
+    if counts {
+        println!("{:>7} {:>7} {:>7}  token", "total", "whole", "partial");
+        for (token, count) in count(tokens) {
+            let total = count.whole + count.partial;
+            println!(
+                "{total:>7} {:>7} {:>7}  {token}",
+                count.whole, count.partial
+            );
+        }
+    } else {
+        // Lines and columns start at 0, the same as in the index.
+        println!(
+            "{:>5} {:>5} {:>8} {:>8} {:>6}  {:<7}  token",
+            "line", "col", "position", "offset", "length", "kind"
+        );
+        for token in tokens {
+            let kind = if token.partial { "partial" } else { "whole" };
+            println!(
+                "{:>5} {:>5} {:>8} {:>8} {:>6}  {kind:<7}  {}",
+                token.line, token.column, token.position, token.offset, token.length, token.token
+            );
+        }
+    }
+}
+
+fn to_json(name: &str, tokens: &[Token], counts: bool) -> Value {
+    if counts {
+        let counts: Vec<_> = count(tokens)
+            .into_iter()
+            .map(|(token, count)| {
+                json!({"token": token, "whole": count.whole, "partial": count.partial})
+            })
+            .collect();
+        json!({"file": name, "counts": counts})
+    } else {
+        let tokens: Vec<_> = tokens
+            .iter()
+            .map(|token| {
+                json!({
+                    "token": token.token,
+                    "position": token.position,
+                    "offset": token.offset,
+                    "length": token.length,
+                    "line": token.line,
+                    "column": token.column,
+                    "partial": token.partial,
+                })
+            })
+            .collect();
+        json!({"file": name, "tokens": tokens})
     }
 }
 
-fn main() {
-    let mut printer = TokenPrinter {};
-    parse_text("foo bar baz", &mut printer);
+fn main() -> Result<()> {
+    let mut args = Args::parse();
+    if args.files.is_empty() {
+        args.files.push(PathBuf::from("-"));
+    }
+
+    // The same analyzer as the tangerine command, so these are the terms it stores.
+    let analyzer = Analyzer::normalized();
+    let mut output = Vec::new();
+    for (i, file) in args.files.iter().enumerate() {
+        let text = read_input(file)?;
+        let mut collector = TokenCollector { tokens: Vec::new() };
+        analyzer.analyze(&text, &mut collector);
+
+        let name = file.display().to_string();
+        if args.json {
+            output.push(to_json(&name, &collector.tokens, args.counts));
+        } else {
+            if i > 0 {
+                println!();
+            }
+            print_text(&name, &collector.tokens, args.counts, args.files.len() > 1);
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    }
+    Ok(())
 }
@@ -0,0 +1,351 @@
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::iter::zip;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use fjall::Config;
+use serde_json::json;
+use tangerine::{
+    analyzer::Analyzer,
+    crawl::{CrawlEvent, CrawlOptions, Crawler, file_document_path},
+    error::Error,
+    grep::{grep_lines, highlight},
+    index::{IndexOptions, InvertedIndex},
+    scorer::Bm25fScorer,
+    snippet::{SnippetOptions, snippet},
+    store::IndexStore,
+};
+
+#[derive(Parser)]
+#[command(about = "A simple inverted-index-based search engine")]
+struct Args {
+    /// Where the index is stored.
+    #[arg(long, global = true, default_value = ".tangerine")]
+    index_dir: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Brings the index up to date with the files under a directory.
+    /// Documents for files under it that are no longer there are removed. Documents are
+    /// named by the full path of the directory followed by the path within it, so several
+    /// directories can share an index.
+    Index {
+        dir: PathBuf,
+
+        /// Only index files matching this glob. May be repeated.
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// Skip files and directories matching this glob. May be repeated.
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// Print every file that is indexed or removed.
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Store the text of each file that is indexed, for search snippets. Once an index
+        /// stores text, it keeps doing so.
+        #[arg(long)]
+        store_content: bool,
+    },
+
+    /// Searches the index for documents matching a query, best matches first.
+    Search {
+        /// Words and "quoted phrases", which can be required with +, excluded with -, or
+        /// limited to path: or body:. Excluding only works along with other words.
+        query: String,
+
+        /// The maximum number of results to print.
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(1..))]
+        limit: i32,
+
+        /// Print the results as JSON, with where the query's words are in each file.
+        #[arg(long)]
+        json: bool,
+
+        /// Print each line that matched, like grep, as path:line:column:text.
+        #[arg(short, long, conflicts_with = "json")]
+        grep: bool,
+
+        /// Print a snippet of each file around the matches. The text of the files must have
+        /// been stored with index --store-content.
+        #[arg(short, long, conflicts_with_all = ["json", "grep"])]
+        snippets: bool,
+
+        /// The directory relative paths in the index are read from, for reading the matched
+        /// lines if the text of the files wasn't stored. Indexed directories are stored with
+        /// their full paths.
+        #[arg(long, default_value = ".")]
+        root: PathBuf,
+    },
+
+    /// Prints statistics about the index.
+    Stats,
+
+    /// Removes the document for a file from the index. The file doesn't have to exist.
+    Rm { path: String },
+}
+
+// If rebuild is set, an index that was built with a different analyzer is cleared, so that
+// every file is indexed again. Otherwise, that's an error.
+fn open_index(index_dir: &Path, mut options: IndexOptions, rebuild: bool) -> Result<InvertedIndex> {
+    let keyspace = Config::new(index_dir)
+        .open()
+        .with_context(|| format!("unable to open index at {}", index_dir.display()))?;
+    match InvertedIndex::with_options(&keyspace, options.clone()) {
+        Err(Error::AnalyzerMismatch {}) if rebuild => {
+            eprintln!(
+                "the index was built with a different analyzer, so it's being rebuilt. \
+                 Any other directories in it need to be indexed again."
+            );
+            let store = IndexStore::new(&keyspace)?;
+            options.store_content |= store.stats().get_settings()?.store_content;
+            store.delete(&keyspace)?;
+            Ok(InvertedIndex::with_options(&keyspace, options)?)
+        }
+        Err(e @ Error::AnalyzerMismatch {}) => Err(e).context("run tangerine index again"),
+        result => Ok(result?),
+    }
+}
+
+fn sync_dir(
+    index: &InvertedIndex,
+    dir: PathBuf,
+    options: CrawlOptions,
+    verbose: bool,
+) -> Result<ExitCode> {
+    let crawler = Crawler::new(dir, &options)?;
+    let report = crawler.sync(index, |event| match event {
+        CrawlEvent::Indexed { path, .. } if verbose => println!("indexed {path}"),
+        CrawlEvent::Removed { path } if verbose => println!("removed {path}"),
+        CrawlEvent::SkippedBinary { path } if verbose => println!("skipped {path}"),
+        CrawlEvent::Failed { path, error } => eprintln!("{path}: {error}"),
+        _ => {}
+    })?;
+
+    println!(
+        "{} added, {} updated, {} unchanged, {} removed, {} binary, {} failed",
+        report.added,
+        report.updated,
+        report.unchanged,
+        report.removed,
+        report.skipped_binary,
+        report.errors.len()
+    );
+    Ok(if report.errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+// How search results are printed.
+struct SearchOutput {
+    json: bool,
+    grep: bool,
+    snippets: bool,
+    root: PathBuf,
+}
+
+fn search(
+    index: &InvertedIndex,
+    query: &str,
+    limit: i32,
+    output: &SearchOutput,
+) -> Result<ExitCode> {
+    let results = index.search_string(query, Bm25fScorer::default(), limit)?;
+
+    // Where the terms that matched each result are in its body.
+    let mut matches = Vec::new();
+    for result in results.iter() {
+        let terms: Vec<String> = result
+            .matched_terms
+            .iter()
+            .map(|matched| matched.term.clone())
+            .collect();
+        matches.push(index.matches(result.id, &terms)?);
+    }
+
+    if output.json {
+        let results: Vec<_> = zip(results.iter(), matches.iter())
+            .enumerate()
+            .map(|(rank, (result, matches))| {
+                let terms: Vec<_> = result
+                    .matched_terms
+                    .iter()
+                    .map(|matched| {
+                        json!({
+                            "term": matched.term,
+                            "body_count": matched.body_count,
+                            "path_count": matched.path_count,
+                        })
+                    })
+                    .collect();
+                let matches: Vec<_> = matches
+                    .iter()
+                    .map(|m| {
+                        json!({
+                            "term": m.term,
+                            "line": m.location.line + 1,
+                            "column": m.location.column + 1,
+                            "offset": m.location.offset,
+                            "length": m.location.length,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "rank": rank + 1,
+                    "id": result.id.to_string(),
+                    "score": result.score,
+                    "path": result.document.path,
+                    "terms": terms,
+                    "matches": matches,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else if output.grep {
+        let (start, end) = highlight_markers();
+        for (result, matches) in zip(results.iter(), matches.iter()) {
+            let path = &result.document.path;
+            let text = match index.content(result.id)? {
+                Some(text) => text,
+                None => match fs::read_to_string(output.root.join(path)) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        eprintln!("{path}: {e}");
+                        continue;
+                    }
+                },
+            };
+            let lines = grep_lines(&text, matches);
+            if lines.is_empty() {
+                // The match was in the path, or the file changed since it was indexed.
+                println!("{path}");
+            }
+            for line in lines.iter() {
+                println!(
+                    "{path}:{}:{}:{}",
+                    line.line + 1,
+                    line.column + 1,
+                    highlight(line, start, end)
+                );
+            }
+        }
+    } else if output.snippets {
+        let (start, end) = highlight_markers();
+        let options = SnippetOptions {
+            start: start.to_string(),
+            end: end.to_string(),
+            ..SnippetOptions::default()
+        };
+        for (result, matches) in zip(results.iter(), matches.iter()) {
+            println!("{}", result.document.path);
+            if let Some(text) = index.content(result.id)? {
+                println!("    {}", snippet(&text, matches, &options));
+            }
+        }
+    } else {
+        for result in results.iter() {
+            println!("{}", result.document.path);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Matches are shown in bold red, if the output is going to a terminal.
+fn highlight_markers() -> (&'static str, &'static str) {
+    if io::stdout().is_terminal() {
+        ("\x1b[1;31m", "\x1b[0m")
+    } else {
+        ("", "")
+    }
+}
+
+fn stats(index: &InvertedIndex) -> Result<ExitCode> {
+    let stats = index.collection_stats()?;
+    println!("documents: {}", stats.document_count);
+    println!("total words: {}", stats.total_length);
+    println!("average words: {:.1}", stats.average_length());
+    println!("average path words: {:.1}", stats.average_path_length());
+    Ok(ExitCode::SUCCESS)
+}
+
+// The path can be spelled any way that finds the file, like the directory given to index.
+// If that isn't in the index, it's looked up exactly as given.
+fn rm(index: &InvertedIndex, path: &str) -> Result<ExitCode> {
+    let mut id = None;
+    if let Ok(document_path) = file_document_path(Path::new(path)) {
+        id = index.lookup_path(&document_path)?;
+    }
+    if id.is_none() {
+        id = index.lookup_path(path)?;
+    }
+    match id {
+        Some(id) if index.delete_document(id)? => Ok(ExitCode::SUCCESS),
+        _ => {
+            eprintln!("{path} is not in the index");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    let options = IndexOptions {
+        store_content: matches!(
+            args.command,
+            Command::Index {
+                store_content: true,
+                ..
+            }
+        ),
+        // Searches ignore case and compatibility forms, like "ﬁ" for "fi".
+        analyzer: Arc::new(Analyzer::normalized()),
+    };
+    let rebuild = matches!(args.command, Command::Index { .. });
+    if !rebuild && !args.index_dir.is_dir() {
+        anyhow::bail!(
+            "there's no index at {}, run tangerine index first",
+            args.index_dir.display()
+        );
+    }
+    let index = open_index(&args.index_dir, options, rebuild)?;
+
+    match args.command {
+        Command::Index {
+            dir,
+            include,
+            exclude,
+            verbose,
+            ..
+        } => sync_dir(&index, dir, CrawlOptions { include, exclude }, verbose),
+        Command::Search {
+            query,
+            limit,
+            json,
+            grep,
+            snippets,
+            root,
+        } => {
+            let output = SearchOutput {
+                json,
+                grep,
+                snippets,
+                root,
+            };
+            search(&index, &query, limit, &output)
+        }
+        Command::Stats => stats(&index),
+        Command::Rm { path } => rm(&index, &path),
+    }
+}
@@ -0,0 +1,781 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+
+use crate::{
+    error::Error,
+    index::{DocumentId, InvertedIndex, content_hash},
+    writer::{DEFAULT_MEMORY_BUDGET, IndexWriter},
+};
+
+// Files with a NUL byte in this many bytes at the start are treated as binary, like git does.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+#[derive(Debug, Default, Clone)]
+pub struct CrawlOptions {
+    pub include: Vec<String>, // if not empty, only files matching one of these globs are indexed
+    pub exclude: Vec<String>, // files and directories matching any of these globs are skipped
+}
+
+// What happened to each file the crawler found.
+#[derive(Debug)]
+pub enum CrawlEvent<'a> {
+    Indexed { path: &'a str, id: DocumentId },
+    Unchanged { path: &'a str },
+    Removed { path: &'a str },
+    SkippedBinary { path: &'a str },
+    Failed { path: &'a str, error: &'a Error },
+}
+
+// The totals for a whole crawl.
+#[derive(Debug, Default)]
+pub struct CrawlReport {
+    pub indexed: usize,
+    pub skipped_binary: usize,
+    pub errors: Vec<(String, Error)>, // the relative path of each file that failed, and why
+}
+
+// The totals for a sync.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub skipped_binary: usize,
+    pub errors: Vec<(String, Error)>, // the relative path of each file that failed, and why
+}
+
+pub(crate) enum FileOutcome {
+    Added(DocumentId),
+    Updated(DocumentId),
+    Unchanged,
+    Binary,
+}
+
+// Walks a directory tree, respecting .gitignore and .ignore files, and indexes the files.
+// Document paths are the full path of the root, followed by the path relative to it, using
+// / as the separator, like "/home/me/proj/src/main.rs" for a root of "src". That way, the
+// same file has the same path however the root is spelled, several roots can share an index,
+// and each crawl only removes documents under its own root.
+pub struct Crawler {
+    root: PathBuf,
+    prefix: String, // what the path of every document under the root starts with
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs.iter() {
+        builder.add(Glob::new(glob)?);
+    }
+    Ok(builder.build()?)
+}
+
+// The path relative to root, with / between each part.
+pub(crate) fn relative_path(root: &Path, path: &Path) -> Result<String, Error> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+    match parts {
+        Some(parts) => Ok(parts.join("/")),
+        None => Err(Error::InvalidPath {
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+// The root with / between each part and at the end.
+fn path_prefix(root: &Path) -> Result<String, Error> {
+    let mut prefix = String::new();
+    for component in root.components() {
+        let part = match component {
+            Component::CurDir => continue,
+            Component::RootDir => "",
+            part => part
+                .as_os_str()
+                .to_str()
+                .ok_or_else(|| Error::InvalidPath {
+                    path: root.to_path_buf(),
+                })?,
+        };
+        prefix.push_str(part);
+        prefix.push('/');
+    }
+    Ok(prefix)
+}
+
+// The document path a crawl would give the file at path, whether or not it still exists,
+// as long as its directory does.
+pub fn file_document_path(path: &Path) -> Result<String, Error> {
+    let invalid = || Error::InvalidPath {
+        path: path.to_path_buf(),
+    };
+    let name = path.file_name().ok_or_else(invalid)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = path_prefix(&fs::canonicalize(dir)?)?;
+    Ok(format!("{prefix}{}", name.to_str().ok_or_else(invalid)?))
+}
+
+fn is_binary(contents: &[u8]) -> bool {
+    let start = &contents[..contents.len().min(BINARY_CHECK_LENGTH)];
+    start.contains(&0)
+}
+
+fn modified_time(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+impl Crawler {
+    pub fn new(root: impl AsRef<Path>, options: &CrawlOptions) -> Result<Self, Error> {
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&options.include)?)
+        };
+        let exclude = build_glob_set(&options.exclude)?;
+        let root = fs::canonicalize(root)?;
+        Ok(Crawler {
+            prefix: path_prefix(&root)?,
+            root,
+            include,
+            exclude,
+        })
+    }
+
+    // The document path for a path relative to the root.
+    pub(crate) fn document_path(&self, relative: &str) -> String {
+        format!("{}{relative}", self.prefix)
+    }
+
+    // The document path of the file or directory a walk error is about, or an empty
+    // string if it doesn't say, or if it's about the root.
+    pub(crate) fn walk_error_path(&self, error: &Error) -> String {
+        match error {
+            Error::WalkError(ignore::Error::WithPath { path, .. }) => {
+                match relative_path(&self.root, path) {
+                    Ok(relative) if !relative.is_empty() => self.document_path(&relative),
+                    _ => String::new(),
+                }
+            }
+            _ => String::new(),
+        }
+    }
+
+    // Returns every file that should be indexed, with its document path, in sorted order.
+    // Errors reading directories are returned along with the files.
+    pub fn files(&self) -> impl Iterator<Item = Result<(String, PathBuf), Error>> + '_ {
+        self.walk(None)
+    }
+
+    // Like files, but if target is set, only returns target and the files under it.
+    // The walk still starts at the root, so ignore files and excluded directories above
+    // the target apply to it.
+    pub(crate) fn walk(
+        &self,
+        target: Option<&Path>,
+    ) -> impl Iterator<Item = Result<(String, PathBuf), Error>> + '_ {
+        let root = self.root.clone();
+        let exclude = self.exclude.clone();
+        let target = target.map(|target| target.to_path_buf());
+        let walker = WalkBuilder::new(&self.root)
+            // Respect .gitignore files even if the tree isn't a git repo.
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                if let Some(target) = &target
+                    && !target.starts_with(entry.path())
+                    && !entry.path().starts_with(target)
+                {
+                    return false;
+                }
+                match relative_path(&root, entry.path()) {
+                    Ok(path) => path.is_empty() || !exclude.is_match(&path),
+                    Err(_) => true,
+                }
+            })
+            .build();
+
+        walker.filter_map(|result| {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                return None;
+            }
+            let path = match relative_path(&self.root, entry.path()) {
+                Ok(path) => path,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(include) = &self.include
+                && !include.is_match(&path)
+            {
+                return None;
+            }
+            Some(Ok((self.document_path(&path), entry.into_path())))
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // What the path of every document under the root starts with.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    // Indexes every file under the root, replacing any document with the same path.
+    // A file that can't be read doesn't stop the crawl. It's reported to on_event and
+    // included in the report, along with every other file. Files are added with an
+    // IndexWriter, so they're written in batches.
+    pub fn index(
+        &self,
+        index: &InvertedIndex,
+        mut on_event: impl FnMut(CrawlEvent),
+    ) -> Result<CrawlReport, Error> {
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        let mut report = CrawlReport::default();
+        for result in self.files() {
+            let (path, result) = match result {
+                Ok((path, full_path)) => {
+                    let result = self.index_file(&mut writer, &path, &full_path, false);
+                    (path, result)
+                }
+                Err(e) => (self.walk_error_path(&e), Err(e)),
+            };
+            match result {
+                Ok(FileOutcome::Added(id) | FileOutcome::Updated(id)) => {
+                    on_event(CrawlEvent::Indexed { path: &path, id });
+                    report.indexed += 1;
+                }
+                Ok(FileOutcome::Unchanged) => on_event(CrawlEvent::Unchanged { path: &path }),
+                Ok(FileOutcome::Binary) => {
+                    on_event(CrawlEvent::SkippedBinary { path: &path });
+                    report.skipped_binary += 1;
+                }
+                Err(e) => {
+                    on_event(CrawlEvent::Failed {
+                        path: &path,
+                        error: &e,
+                    });
+                    report.errors.push((path, e));
+                }
+            }
+        }
+        writer.finish()?;
+        Ok(report)
+    }
+
+    // Brings the index up to date with the files under the root. Files whose size and
+    // modification time match the index are skipped without being read, and files whose
+    // contents hash the same aren't re-indexed. Documents under the root whose files are
+    // gone are removed.
+    // Like index, a file that can't be read doesn't stop the sync, and it isn't removed.
+    pub fn sync(
+        &self,
+        index: &InvertedIndex,
+        mut on_event: impl FnMut(CrawlEvent),
+    ) -> Result<SyncReport, Error> {
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        let mut report = SyncReport::default();
+        let mut seen = HashSet::new();
+        for result in self.files() {
+            let (path, result) = match result {
+                Ok((path, full_path)) => {
+                    let result = self.index_file(&mut writer, &path, &full_path, true);
+                    (path, result)
+                }
+                Err(e) => (self.walk_error_path(&e), Err(e)),
+            };
+            match result {
+                Ok(FileOutcome::Added(id)) => {
+                    on_event(CrawlEvent::Indexed { path: &path, id });
+                    report.added += 1;
+                }
+                Ok(FileOutcome::Updated(id)) => {
+                    on_event(CrawlEvent::Indexed { path: &path, id });
+                    report.updated += 1;
+                }
+                Ok(FileOutcome::Unchanged) => {
+                    on_event(CrawlEvent::Unchanged { path: &path });
+                    report.unchanged += 1;
+                }
+                Ok(FileOutcome::Binary) => {
+                    on_event(CrawlEvent::SkippedBinary { path: &path });
+                    report.skipped_binary += 1;
+                    // Don't keep a document for a file that's binary now.
+                    continue;
+                }
+                Err(e) => {
+                    on_event(CrawlEvent::Failed {
+                        path: &path,
+                        error: &e,
+                    });
+                    report.errors.push((path.clone(), e));
+                }
+            }
+            seen.insert(path);
+        }
+
+        // If a whole directory couldn't be read, keep everything that was in it.
+        let kept = |path: &str| {
+            seen.contains(path)
+                || report.errors.iter().any(|(error_path, _)| {
+                    error_path.is_empty()
+                        || path
+                            .strip_prefix(error_path.as_str())
+                            .is_some_and(|rest| rest.starts_with('/'))
+                })
+        };
+        let mut vanished = Vec::new();
+        for result in index.paths(&self.prefix) {
+            let (path, id) = result?;
+            if !kept(&path) {
+                vanished.push((id, path));
+            }
+        }
+        for (id, path) in vanished {
+            // A file that turned binary was already removed.
+            if writer.delete_document(id)? {
+                on_event(CrawlEvent::Removed { path: &path });
+                report.removed += 1;
+            }
+        }
+        writer.finish()?;
+        Ok(report)
+    }
+
+    // Indexes one file. If check is true, the file is only re-indexed if it's changed.
+    // Changes aren't in the index until the writer is flushed.
+    pub(crate) fn index_file(
+        &self,
+        writer: &mut IndexWriter,
+        path: &str,
+        full_path: &Path,
+        check: bool,
+    ) -> Result<FileOutcome, Error> {
+        let index = writer.index();
+        let metadata = fs::metadata(full_path)?;
+        let modified = modified_time(&metadata);
+        let existing = match index.lookup_path(path)? {
+            Some(id) => index.document(id)?.map(|doc_data| (id, doc_data)),
+            None => None,
+        };
+
+        if check
+            && let Some((_, doc_data)) = &existing
+            && modified != 0
+            && doc_data.modified == modified
+            && doc_data.size == metadata.len()
+        {
+            return Ok(FileOutcome::Unchanged);
+        }
+
+        let contents = fs::read(full_path)?;
+        if is_binary(&contents) {
+            if let Some((id, _)) = existing {
+                writer.delete_document(id)?;
+            }
+            return Ok(FileOutcome::Binary);
+        }
+
+        if check
+            && let Some((id, doc_data)) = &existing
+            && doc_data.size == contents.len() as u64
+            && doc_data.content_hash == content_hash(&contents)
+        {
+            writer.set_modified(*id, modified)?;
+            return Ok(FileOutcome::Unchanged);
+        }
+
+        let id = writer.upsert_file(path, &mut &contents[..], modified)?;
+        match existing {
+            Some(_) => Ok(FileOutcome::Updated(id)),
+            None => Ok(FileOutcome::Added(id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scorer::Bm25Scorer, store::IndexStore};
+    use fjall::Config;
+
+    // Replaces the directory with the given files.
+    fn make_tree(root: &Path, files: &[(&str, &[u8])]) {
+        let _ = fs::remove_dir_all(root);
+        for (path, contents) in files.iter() {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    // The files the crawler finds, relative to its root.
+    fn crawled_files(crawler: &Crawler) -> Vec<String> {
+        crawler
+            .files()
+            .map(|result| relative(crawler, &result.unwrap().0))
+            .collect()
+    }
+
+    fn relative(crawler: &Crawler, path: &str) -> String {
+        path.strip_prefix(crawler.prefix()).unwrap().to_string()
+    }
+
+    // Describes an event, with the path relative to the crawler's root.
+    fn describe(crawler: &Crawler, event: CrawlEvent) -> String {
+        match event {
+            CrawlEvent::Indexed { path, .. } => format!("indexed {}", relative(crawler, path)),
+            CrawlEvent::Unchanged { path } => format!("unchanged {}", relative(crawler, path)),
+            CrawlEvent::Removed { path } => format!("removed {}", relative(crawler, path)),
+            CrawlEvent::SkippedBinary { path } => format!("binary {}", relative(crawler, path)),
+            CrawlEvent::Failed { path, .. } => format!("failed {}", relative(crawler, path)),
+        }
+    }
+
+    #[test]
+    fn test_path_prefix() -> Result<(), Error> {
+        assert_eq!("src/", path_prefix(Path::new("./src/"))?);
+        assert_eq!("../a/b/", path_prefix(Path::new("../a/b"))?);
+        assert_eq!("/tmp/a/", path_prefix(Path::new("/tmp/a"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_document_path() -> Result<(), Error> {
+        let root = Path::new("/tmp/tangerine/test_file_document_path");
+        make_tree(root, &[("dir/a.txt", b"apple")]);
+
+        let crawler = Crawler::new(root, &CrawlOptions::default())?;
+        let expected = crawler.document_path("dir/a.txt");
+        assert_eq!(expected, file_document_path(&root.join("dir/a.txt"))?);
+        assert_eq!(
+            expected,
+            file_document_path(&root.join("dir/../dir/./a.txt"))?
+        );
+
+        // Files that were deleted can still be named.
+        assert_eq!(
+            crawler.document_path("dir/gone.txt"),
+            file_document_path(&root.join("dir/gone.txt"))?
+        );
+        assert!(file_document_path(&root.join("missing/a.txt")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawler_files() -> Result<(), Error> {
+        let root = Path::new("/tmp/tangerine/test_crawler_files");
+        make_tree(
+            root,
+            &[
+                (".gitignore", b"ignored.txt\n"),
+                ("src/.ignore", b"generated/\n"),
+                ("src/main.rs", b"fn main() {}"),
+                ("src/lib.rs", b"pub mod a;"),
+                ("src/generated/out.rs", b"// generated"),
+                ("ignored.txt", b"ignored"),
+                ("notes.txt", b"some notes"),
+                ("target/debug/app.rs", b"built"),
+                (".hidden", b"hidden"),
+            ],
+        );
+
+        let crawler = Crawler::new(root, &CrawlOptions::default())?;
+        assert_eq!("/tmp/tangerine/test_crawler_files/", crawler.prefix());
+        assert_eq!(
+            vec![
+                "notes.txt",
+                "src/lib.rs",
+                "src/main.rs",
+                "target/debug/app.rs"
+            ],
+            crawled_files(&crawler)
+        );
+
+        let options = CrawlOptions {
+            include: vec!["*.rs".to_string()],
+            exclude: vec!["target".to_string(), "**/main.rs".to_string()],
+        };
+        let crawler = Crawler::new(root, &options)?;
+        assert_eq!(vec!["src/lib.rs"], crawled_files(&crawler));
+
+        let options = CrawlOptions {
+            include: vec!["[".to_string()],
+            ..Default::default()
+        };
+        assert!(Crawler::new(root, &options).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawler_index() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_crawler_index")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let root = Path::new("/tmp/tangerine/test_crawler_index_tree");
+        make_tree(
+            root,
+            &[
+                ("a/apple.txt", b"apple pie"),
+                ("banana.txt", b"banana split"),
+                ("image.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+                ("latin1.txt", b"caf\xe9"),
+            ],
+        );
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let crawler = Crawler::new(root, &CrawlOptions::default())?;
+        let path = |relative: &str| crawler.document_path(relative);
+        let mut events = Vec::new();
+        let report = crawler.index(&index, |event| events.push(describe(&crawler, event)))?;
+
+        assert_eq!(
+            vec![
+                "indexed a/apple.txt",
+                "indexed banana.txt",
+                "binary image.png",
+                "indexed latin1.txt",
+            ],
+            events
+        );
+        assert_eq!(3, report.indexed);
+        assert_eq!(1, report.skipped_binary);
+        assert!(report.errors.is_empty());
+
+        // Text that isn't UTF-8 is decoded lossily.
+        let latin1 = index.search(&["caf".to_string()], Bm25Scorer::default(), 10)?;
+        assert_eq!(1, latin1.len());
+        assert_eq!(path("latin1.txt"), latin1[0].document.path);
+        assert_eq!(4, latin1[0].document.size);
+
+        let apple = index.lookup_path(&path("a/apple.txt"))?.unwrap();
+        let results = index.search(&["pie".to_string()], Bm25Scorer::default(), 10)?;
+        assert_eq!(1, results.len());
+        assert_eq!(apple, results[0].id);
+        assert_eq!(path("a/apple.txt"), results[0].document.path);
+
+        // Crawling again replaces the documents instead of adding them twice.
+        let report = crawler.index(&index, |_| {})?;
+        assert_eq!(3, report.indexed);
+        assert_eq!(3, index.collection_stats()?.document_count);
+
+        // The size and hash are of the file's bytes, so a sync sees it hasn't changed.
+        let report = crawler.sync(&index, |_| {})?;
+        assert_eq!(3, report.unchanged);
+        assert_eq!(0, report.added + report.updated + report.removed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawler_sync() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_crawler_sync")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let root = Path::new("/tmp/tangerine/test_crawler_sync_tree");
+        make_tree(
+            root,
+            &[
+                ("same.txt", b"apple"),
+                ("touched.txt", b"banana"),
+                ("changed.txt", b"cherry"),
+                ("deleted.txt", b"date"),
+                ("later_binary.txt", b"elderberry"),
+            ],
+        );
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let crawler = Crawler::new(root, &CrawlOptions::default())?;
+        let path = |relative: &str| crawler.document_path(relative);
+        let report = crawler.sync(&index, |_| {})?;
+        assert_eq!(5, report.added);
+        assert_eq!(0, report.unchanged);
+
+        let same = index.lookup_path(&path("same.txt"))?.unwrap();
+        let doc_data = index.document(same)?.unwrap();
+        assert_eq!(5, doc_data.size);
+        assert_eq!(content_hash(b"apple"), doc_data.content_hash);
+        assert_ne!(0, doc_data.modified);
+
+        // Rewriting a file with the same contents changes its modification time.
+        let touched = index.lookup_path(&path("touched.txt"))?.unwrap();
+        let old_modified = index.document(touched)?.unwrap().modified;
+        let file = fs::File::options()
+            .write(true)
+            .open(root.join("touched.txt"))?;
+        file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1_000_000))?;
+        drop(file);
+
+        fs::write(root.join("changed.txt"), b"cherry pie")?;
+        fs::remove_file(root.join("deleted.txt"))?;
+        fs::write(root.join("later_binary.txt"), b"\0\0\0")?;
+        fs::write(root.join("new.txt"), b"fig")?;
+
+        let mut events = Vec::new();
+        let report = crawler.sync(&index, |event| events.push(describe(&crawler, event)))?;
+        assert_eq!(
+            vec![
+                "indexed changed.txt",
+                "binary later_binary.txt",
+                "indexed new.txt",
+                "unchanged same.txt",
+                "unchanged touched.txt",
+                "removed deleted.txt",
+            ],
+            events
+        );
+        assert_eq!(1, report.added);
+        assert_eq!(1, report.updated);
+        assert_eq!(2, report.unchanged);
+        assert_eq!(1, report.removed);
+        assert_eq!(1, report.skipped_binary);
+        assert!(report.errors.is_empty());
+
+        // The touched file kept its id, but got the new modification time.
+        assert_eq!(Some(touched), index.lookup_path(&path("touched.txt"))?);
+        let new_modified = index.document(touched)?.unwrap().modified;
+        assert_ne!(old_modified, new_modified);
+        assert_eq!(1_000_000_000_000_000, new_modified);
+
+        assert_eq!(Some(same), index.lookup_path(&path("same.txt"))?);
+        assert_eq!(None, index.lookup_path(&path("deleted.txt"))?);
+        assert_eq!(None, index.lookup_path(&path("later_binary.txt"))?);
+        assert_eq!(4, index.collection_stats()?.document_count);
+        let pie = index.search(&["pie".to_string()], Bm25Scorer::default(), 10)?;
+        assert_eq!(1, pie.len());
+        assert_eq!(path("changed.txt"), pie[0].document.path);
+
+        // Nothing changes the second time.
+        let report = crawler.sync(&index, |_| {})?;
+        assert_eq!(4, report.unchanged);
+        assert_eq!(0, report.added + report.updated + report.removed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawler_sync_only_removes_under_root() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_crawler_sync_only_removes_under_root")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let root = Path::new("/tmp/tangerine/test_crawler_sync_roots");
+        make_tree(
+            root,
+            &[
+                ("a/same.txt", b"apple"),
+                ("a/deleted.txt", b"banana"),
+                ("ab/same.txt", b"cherry"),
+            ],
+        );
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let other = index.add_document("other.txt", &mut "date".as_bytes())?;
+        let a = Crawler::new(root.join("a"), &CrawlOptions::default())?;
+        let ab = Crawler::new(root.join("ab"), &CrawlOptions::default())?;
+        a.sync(&index, |_| {})?;
+        ab.sync(&index, |_| {})?;
+        assert_eq!(4, index.collection_stats()?.document_count);
+
+        // The same relative path under each root is a different document.
+        let a_same = index.lookup_path(&a.document_path("same.txt"))?;
+        let ab_same = index.lookup_path(&ab.document_path("same.txt"))?;
+        assert!(a_same.is_some() && ab_same.is_some());
+        assert_ne!(a_same, ab_same);
+
+        fs::remove_file(root.join("a/deleted.txt"))?;
+        let mut events = Vec::new();
+        let report = a.sync(&index, |event| events.push(describe(&a, event)))?;
+        assert_eq!(vec!["unchanged same.txt", "removed deleted.txt"], events);
+        assert_eq!(1, report.removed);
+
+        // Documents from other roots, or added some other way, are left alone.
+        assert_eq!(ab_same, index.lookup_path(&ab.document_path("same.txt"))?);
+        assert_eq!(Some(other), index.lookup_path("other.txt")?);
+        assert_eq!(3, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crawler_sync_current_dir() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_crawler_sync_current_dir")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let root = Path::new("/tmp/tangerine/test_crawler_sync_current_dir_tree");
+        make_tree(
+            root,
+            &[
+                ("a/same.txt", b"apple"),
+                ("a/deleted.txt", b"banana"),
+                ("b/same.txt", b"cherry"),
+            ],
+        );
+
+        // No other test depends on the current directory.
+        std::env::set_current_dir(root.join("a"))?;
+        let index = InvertedIndex::new(&keyspace)?;
+        let other = index.add_document("other.txt", &mut "date".as_bytes())?;
+        let a = Crawler::new(".", &CrawlOptions::default())?;
+        let b = Crawler::new(root.join("b"), &CrawlOptions::default())?;
+        assert_eq!(
+            a.prefix(),
+            Crawler::new(root.join("a"), &CrawlOptions::default())?.prefix()
+        );
+        assert_eq!(
+            a.prefix(),
+            Crawler::new("../a/", &CrawlOptions::default())?.prefix()
+        );
+        a.sync(&index, |_| {})?;
+        b.sync(&index, |_| {})?;
+        assert_eq!(4, index.collection_stats()?.document_count);
+
+        // Spelling the root another way finds the same documents.
+        let mut events = Vec::new();
+        let absolute = Crawler::new(root.join("a"), &CrawlOptions::default())?;
+        absolute.sync(&index, |event| events.push(describe(&absolute, event)))?;
+        assert_eq!(vec!["unchanged deleted.txt", "unchanged same.txt"], events);
+        assert_eq!(4, index.collection_stats()?.document_count);
+
+        fs::remove_file(root.join("a/deleted.txt"))?;
+        let mut events = Vec::new();
+        let report = a.sync(&index, |event| events.push(describe(&a, event)))?;
+        assert_eq!(vec!["unchanged same.txt", "removed deleted.txt"], events);
+        assert_eq!(1, report.removed);
+
+        // Documents from other roots, or added some other way, are left alone.
+        assert!(index.lookup_path(&b.document_path("same.txt"))?.is_some());
+        assert_eq!(Some(other), index.lookup_path("other.txt")?);
+        assert_eq!(3, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+}
@@ -3,6 +3,24 @@ pub enum Error {
     #[error("unable to deserialize data")]
     DeserializationError {},
 
+    #[error("invalid query at {span:?}: {message}")]
+    QuerySyntaxError {
+        message: String,
+        span: std::ops::Range<usize>, // the byte range of the query text with the problem
+    },
+
+    #[error("an IndexWriter on this thread is already changing the index")]
+    WriterOpen {},
+
+    #[error("the index was built with a different analyzer, so it has to be rebuilt")]
+    AnalyzerMismatch {},
+
+    #[error("the file watcher stopped sending changes")]
+    WatcherStopped {},
+
+    #[error("path is not valid unicode: {path:?}")]
+    InvalidPath { path: std::path::PathBuf },
+
     #[error(transparent)]
     FjallError(#[from] fjall::Error),
 
@@ -18,6 +36,18 @@ pub enum Error {
     #[error(transparent)]
     BrotopufError(#[from] brotopuf::DeserializeError),
 
+    #[error(transparent)]
+    GlobError(#[from] globset::Error),
+
+    #[error(transparent)]
+    WalkError(#[from] ignore::Error),
+
+    #[error(transparent)]
+    DecompressError(#[from] lz4_flex::block::DecompressError),
+
+    #[error(transparent)]
+    NotifyError(#[from] notify::Error),
+
     #[error(transparent)]
     InnerError(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
@@ -0,0 +1,129 @@
+use std::ops::Range;
+
+use crate::index::Match;
+
+// One line of a document that contains matches, for printing like grep.
+#[derive(Debug, PartialEq)]
+pub struct GrepLine<'a> {
+    pub line: u64,                     // which line this is, starting at 0
+    pub column: u64,                   // the column of the first match in the line, starting at 0
+    pub text: &'a str,                 // the line, without the line ending
+    pub highlights: Vec<Range<usize>>, // the sorted byte ranges of the matches in text
+}
+
+// Groups matches, sorted by location, into the lines of text that contain them.
+// Overlapping matches are merged, and matches that don't fit the text, because it
+// changed since it was indexed, are skipped.
+pub fn grep_lines<'a>(text: &'a str, matches: &[Match]) -> Vec<GrepLine<'a>> {
+    let mut lines: Vec<GrepLine> = Vec::new();
+    let mut line_start = 0;
+    for m in matches {
+        let start = m.location.offset as usize;
+        let end = start + m.location.length as usize;
+        if text
+            .get(start..end)
+            .is_none_or(|word| word.contains(['\n', '\r']))
+        {
+            continue;
+        }
+
+        match lines.last_mut() {
+            Some(last) if last.line == m.location.line && start >= line_start => {
+                let range = (start - line_start)..(end - line_start);
+                match last.highlights.last_mut() {
+                    Some(previous) if range.start <= previous.end => {
+                        previous.end = previous.end.max(range.end);
+                    }
+                    _ => last.highlights.push(range),
+                }
+            }
+            _ => {
+                line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+                let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+                let line_text = &text[line_start..line_end];
+                let line_text = line_text.strip_suffix('\r').unwrap_or(line_text);
+                let range = (start - line_start)..(end - line_start);
+                lines.push(GrepLine {
+                    line: m.location.line,
+                    column: m.location.column,
+                    text: line_text,
+                    highlights: vec![range],
+                });
+            }
+        }
+    }
+    lines
+}
+
+// Returns the text of the line with each highlighted range wrapped in start and end.
+pub fn highlight(line: &GrepLine, start: &str, end: &str) -> String {
+    let mut result = String::with_capacity(line.text.len());
+    let mut last = 0;
+    for range in line.highlights.iter() {
+        result.push_str(&line.text[last..range.start]);
+        result.push_str(start);
+        result.push_str(&line.text[range.clone()]);
+        result.push_str(end);
+        last = range.end;
+    }
+    result.push_str(&line.text[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Location;
+
+    fn found(term: &str, line: u64, column: u64, offset: u64) -> Match {
+        Match {
+            term: term.to_string(),
+            location: Location {
+                line,
+                column,
+                offset,
+                length: term.len() as u64,
+            },
+        }
+    }
+
+    #[test]
+    fn test_grep_lines() {
+        let text = "fn parse() {\r\n  parseAll(parse_one)\r\n}\r\n";
+        let matches = vec![
+            found("parse", 0, 3, 3),
+            found("parseAll", 1, 2, 16),
+            found("parse", 1, 2, 16),
+            found("parse", 1, 11, 25),
+        ];
+        let lines = grep_lines(text, &matches);
+
+        assert_eq!(2, lines.len());
+        assert_eq!(0, lines[0].line);
+        assert_eq!(3, lines[0].column);
+        assert_eq!("fn parse() {", lines[0].text);
+        assert_eq!(1, lines[0].highlights.len());
+        assert_eq!(3..8, lines[0].highlights[0]);
+
+        assert_eq!(1, lines[1].line);
+        assert_eq!(2, lines[1].column);
+        assert_eq!("  parseAll(parse_one)", lines[1].text);
+        assert_eq!([2..10, 11..16], lines[1].highlights[..]);
+
+        assert_eq!("  [parseAll]([parse]_one)", highlight(&lines[1], "[", "]"));
+    }
+
+    #[test]
+    fn test_grep_lines_changed_text() {
+        // The text is shorter than when it was indexed.
+        let matches = vec![found("apple", 0, 0, 0), found("banana", 0, 6, 6)];
+        let lines = grep_lines("apple", &matches);
+        assert_eq!(1, lines.len());
+        assert_eq!(1, lines[0].highlights.len());
+        assert_eq!(0..5, lines[0].highlights[0]);
+
+        // A match that would split a character is skipped.
+        let lines = grep_lines("é", &[found("x", 0, 0, 1)]);
+        assert!(lines.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use xxhash_rust::xxh3::xxh3_128_with_seed;
+
+use crate::{error::Error, index::DocumentId, store::WriteBatch};
+
+// What an allocator knows about the document it's choosing an id for.
+#[derive(Debug, Clone, Copy)]
+pub struct NewDocument<'a> {
+    pub path: &'a str,
+    pub content_hash: u64, // the xxh3 hash of the body, as in DocumentData
+}
+
+// Chooses the id for each new document.
+// Allocators are only called while holding the index's writer lock, and the batch is only
+// committed if the document is, so an id is never handed out twice.
+pub trait IdAllocator: Send + Sync {
+    fn allocate(&self, batch: &mut WriteBatch, doc: &NewDocument) -> Result<DocumentId, Error>;
+}
+
+// Gives out ids in increasing order, from a counter stored with the index.
+// Ids of deleted documents are never reused.
+#[derive(Debug, Default)]
+pub struct SequentialIds {}
+
+impl IdAllocator for SequentialIds {
+    fn allocate(&self, batch: &mut WriteBatch, _doc: &NewDocument) -> Result<DocumentId, Error> {
+        let id = match batch.get_next_id()? {
+            Some(id) => id,
+            // Indexes from before the counter existed start after their highest id.
+            None => match batch.store().documents().last_id()? {
+                Some(last) => last + 1,
+                None => 0,
+            },
+        };
+        batch.put_next_id(id + 1)?;
+        Ok(id)
+    }
+}
+
+// Gives out random ids, like a version 4 UUID. These don't depend on anything stored in
+// the index, so they're useful when ids from several indexes need to be combined.
+#[derive(Debug, Default)]
+pub struct RandomIds {}
+
+impl IdAllocator for RandomIds {
+    fn allocate(&self, batch: &mut WriteBatch, _doc: &NewDocument) -> Result<DocumentId, Error> {
+        loop {
+            let id = fastrand::u128(..);
+            if batch.claim_id(id)? {
+                return Ok(id);
+            }
+        }
+    }
+}
+
+// Gives out ids from a hash of the document's path and contents, so the same file gets
+// the same id in any index it's added to. If that id is taken, such as by an unchanged
+// file being added again, the hash is retried with a counter until a free id turns up.
+#[derive(Debug, Default)]
+pub struct ContentHashIds {}
+
+impl IdAllocator for ContentHashIds {
+    fn allocate(&self, batch: &mut WriteBatch, doc: &NewDocument) -> Result<DocumentId, Error> {
+        let mut key = Vec::with_capacity(doc.path.len() + 17);
+        key.extend_from_slice(doc.path.as_bytes());
+        key.push(0);
+        key.extend_from_slice(&doc.content_hash.to_be_bytes());
+        let mut attempt: u64 = 0;
+        loop {
+            // A seed of 0 is the same as the plain hash.
+            let id = xxh3_128_with_seed(&key, attempt);
+            if batch.claim_id(id)? {
+                return Ok(id);
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index::DocumentData, store::IndexStore};
+    use fjall::Config;
+
+    const DOC: NewDocument = NewDocument {
+        path: "a.txt",
+        content_hash: 1,
+    };
+
+    #[test]
+    fn test_sequential_ids() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_sequential_ids")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = IndexStore::new(&keyspace)?;
+        let ids = SequentialIds {};
+
+        let mut batch = store.batch();
+        assert_eq!(0, ids.allocate(&mut batch, &DOC)?);
+        assert_eq!(1, ids.allocate(&mut batch, &DOC)?);
+        batch.commit()?;
+
+        // Ids from a batch that's dropped are handed out again.
+        let mut batch = store.batch();
+        assert_eq!(2, ids.allocate(&mut batch, &DOC)?);
+        drop(batch);
+
+        let mut batch = store.batch();
+        assert_eq!(2, ids.allocate(&mut batch, &DOC)?);
+        batch.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequential_ids_start_after_existing_documents() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_sequential_ids_start_after_existing")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = IndexStore::new(&keyspace)?;
+        store.documents().put(41, &DocumentData::default())?;
+
+        let mut batch = store.batch();
+        assert_eq!(42, SequentialIds {}.allocate(&mut batch, &DOC)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_ids() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_random_ids")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = IndexStore::new(&keyspace)?;
+        let mut batch = store.batch();
+        let a = RandomIds {}.allocate(&mut batch, &DOC)?;
+        let b = RandomIds {}.allocate(&mut batch, &DOC)?;
+        assert_ne!(a, b);
+
+        // Ids given out earlier in the batch are taken, as well as committed ones.
+        assert!(!batch.claim_id(a)?);
+        store.documents().put(5, &DocumentData::default())?;
+        assert!(!batch.claim_id(5)?);
+        assert!(batch.claim_id(6)?);
+        assert!(!batch.claim_id(6)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash_ids() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_content_hash_ids")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = IndexStore::new(&keyspace)?;
+        let ids = ContentHashIds {};
+        let mut batch = store.batch();
+        let a = ids.allocate(&mut batch, &DOC)?;
+        let again = ids.allocate(&mut batch, &DOC)?;
+        let other = NewDocument {
+            path: "b.txt",
+            ..DOC
+        };
+        let b = ids.allocate(&mut batch, &other)?;
+        assert_ne!(a, again);
+        assert_ne!(a, b);
+        drop(batch);
+
+        // The same document gets the same id in a new batch.
+        let mut batch = store.batch();
+        assert_eq!(a, ids.allocate(&mut batch, &DOC)?);
+        store.documents().put(a, &DocumentData::default())?;
+        let mut batch = store.batch();
+        assert_eq!(again, ids.allocate(&mut batch, &DOC)?);
+
+        Ok(())
+    }
+}
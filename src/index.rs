@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
 
 use brotopuf::{Deserialize, DeserializeField, Serialize};
 use fjall::Keyspace;
@@ -6,23 +8,98 @@ use ordered_float::OrderedFloat;
 use priority_queue::PriorityQueue;
 
 use crate::{
+    analyzer::Analyzer,
     error::Error,
-    parse::{TokenProcessor, TokenSlice, parse_text},
-    store::{DocumentStore, IndexStore, PostingListStore, TermStore},
+    id::{IdAllocator, NewDocument, SequentialIds},
+    parse::{Occurrence, TokenProcessor, TokenSlice},
+    query::{Field, Query, edit_distance, parse_query_with},
+    store::{DocumentStore, IndexStore, PostingListStore, TermStore, WriteBatch},
 };
 
 pub type DocumentId = u128;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+// The hash stored in DocumentData::content_hash.
+pub fn content_hash(contents: &[u8]) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(contents)
+}
+
+// The most terms a prefix or fuzzy query term will be expanded into.
+pub const MAX_EXPANSIONS: usize = 256;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct DocumentData {
     #[id(0)]
     pub path: String,
 
     #[id(1)]
-    pub length: u64,
+    pub length: u64, // the number of tokens in the body, including pieces of words
+
+    #[id(2)]
+    pub path_length: u64, // the number of tokens in the path, including pieces of words
+
+    #[id(3)]
+    pub size: u64, // the number of bytes in the body
+
+    #[id(4)]
+    pub modified: u64, // when the file was last modified, in nanoseconds since the epoch, or 0
+
+    #[id(5)]
+    pub content_hash: u64, // the xxh3 hash of the body
+}
+
+// Statistics about every document in the index.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CollectionStats {
+    #[id(0)]
+    pub document_count: u64,
+
+    #[id(1)]
+    pub total_length: u64, // the number of tokens in every document's body
+
+    #[id(2)]
+    pub total_path_length: u64, // the number of tokens in every document's path
+}
+
+impl CollectionStats {
+    fn add(&mut self, doc_data: &DocumentData) {
+        self.document_count += 1;
+        self.total_length += doc_data.length;
+        self.total_path_length += doc_data.path_length;
+    }
+
+    fn remove(&mut self, doc_data: &DocumentData) {
+        self.document_count = self.document_count.saturating_sub(1);
+        self.total_length = self.total_length.saturating_sub(doc_data.length);
+        self.total_path_length = self.total_path_length.saturating_sub(doc_data.path_length);
+    }
+
+    pub fn average_length(&self) -> f32 {
+        if self.document_count == 0 {
+            return 0.0;
+        }
+        self.total_length as f32 / self.document_count as f32
+    }
+
+    pub fn average_path_length(&self) -> f32 {
+        if self.document_count == 0 {
+            return 0.0;
+        }
+        self.total_path_length as f32 / self.document_count as f32
+    }
+}
+
+// Settings that apply to the whole index, which are saved with it so that every process
+// that opens it indexes documents the same way.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSettings {
+    #[id(0)]
+    pub store_content: bool,
+
+    #[id(1)]
+    pub analyzer: String, // the id of the analyzer, or empty if it wasn't saved
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TermData {
     #[id(0)]
     pub count: u64, // total number of times this term occurred
@@ -31,22 +108,111 @@ pub struct TermData {
     pub document_count: u64, // total number of documents this term occurred in
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct DocumentTermData {
     #[id(0)]
     pub body_count: u64, // the number of times this term occurs in this doc
 
     #[id(1)]
     pub path_count: u64, // the number of times this term occurs in this doc's path
+
+    #[id(2)]
+    pub body_positions: Vec<u64>, // the sorted word positions of this term in this doc
+
+    #[id(3)]
+    pub path_positions: Vec<u64>, // the sorted word positions of this term in this doc's path
 }
 
-struct DocProcessor {
-    id: DocumentId,
+// Where each occurrence of a term is in the body of a document, in order.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TermLocations {
+    #[id(0)]
+    pub offsets: Vec<u64>, // the byte offset of each occurrence
+
+    #[id(1)]
+    pub lengths: Vec<u64>, // the length of each occurrence in bytes
+
+    #[id(2)]
+    pub lines: Vec<u64>, // the line each occurrence is on
+
+    #[id(3)]
+    pub columns: Vec<u64>, // the character offset of each occurrence in its line
+}
+
+// Where one occurrence of a term is. Lines and columns start at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub line: u64,
+    pub column: u64,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl TermLocations {
+    fn push(&mut self, location: Location) {
+        self.offsets.push(location.offset);
+        self.lengths.push(location.length);
+        self.lines.push(location.line);
+        self.columns.push(location.column);
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Location> + '_ {
+        (0..self.len()).map(|i| Location {
+            line: self.lines[i],
+            column: self.columns[i],
+            offset: self.offsets[i],
+            length: self.lengths[i],
+        })
+    }
+}
+
+// One occurrence of a query term in a document that matched a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub term: String,
+    pub location: Location,
+}
+
+// How many times a query term occurs in a document that matched a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedTerm {
+    pub term: String,
+    pub body_count: u64,
+    pub path_count: u64,
+}
+
+// A document that matched a search.
+#[derive(Debug)]
+pub struct SearchResult {
+    pub id: DocumentId,
+    pub score: f32,
+    pub document: DocumentData,
+    pub matched_terms: Vec<MatchedTerm>, // the query terms in the document, in query order
+}
+
+pub(crate) struct DocProcessor {
+    pub(crate) id: DocumentId,
     path: String,
     in_path: bool,
+    last_counted: Option<(bool, Occurrence)>, // the last place in the text that was counted
     length: u64,
+    path_length: u64,
+    size: u64,
+    pub(crate) modified: u64,
+    content_hash: u64,
+    pub(crate) replace: bool, // whether to replace the document with the same path
+    content: Option<String>,  // the text of the body, if the index stores it
     terms: HashMap<String, TermData>,
     doc_terms: HashMap<String, DocumentTermData>,
+    locations: HashMap<String, TermLocations>,
 }
 
 impl DocProcessor {
@@ -55,25 +221,102 @@ impl DocProcessor {
             id,
             path: path.to_string(),
             in_path: false,
+            last_counted: None,
             length: 0,
+            path_length: 0,
+            size: 0,
+            modified: 0,
+            replace: false,
+            content_hash: 0,
+            content: None,
             terms: HashMap::new(),
             doc_terms: HashMap::new(),
+            locations: HashMap::new(),
         }
     }
 
-    fn finalize(&self, index: &IndexStore) -> Result<(), Error> {
-        let doc_data = DocumentData {
-            path: self.path.clone(),
-            length: self.length,
-        };
-        index.documents().put(self.id, &doc_data)?;
+    // What the id allocator is told about the document.
+    pub(crate) fn new_document(&self) -> NewDocument<'_> {
+        NewDocument {
+            path: &self.path,
+            content_hash: self.content_hash,
+        }
+    }
+
+    // A rough count of the bytes of memory used by the processed document.
+    pub(crate) fn memory_size(&self) -> usize {
+        let per_term =
+            2 * size_of::<String>() + size_of::<TermData>() + size_of::<DocumentTermData>() + 32; // hash table overhead
+        let terms: usize = self
+            .doc_terms
+            .iter()
+            .map(|(term, data)| {
+                2 * term.len()
+                    + per_term
+                    + size_of::<u64>() * (data.body_positions.len() + data.path_positions.len())
+            })
+            .sum();
+        let locations: usize = self
+            .locations
+            .iter()
+            .map(|(term, data)| {
+                term.len()
+                    + size_of::<String>()
+                    + size_of::<TermLocations>()
+                    + 32
+                    + size_of::<Location>() * data.len()
+            })
+            .sum();
+        let content = self.content.as_ref().map_or(0, |text| text.len());
+        size_of::<Self>() + self.path.len() + terms + locations + content
+    }
 
-        for (term, term_data) in self.terms.iter() {
-            index.terms().put(term, term_data)?;
+    // Adds processed documents to the batch. Each term's stats are merged once for all of
+    // the documents, and the postings are written in key order.
+    pub(crate) fn finalize(docs: &[DocProcessor], batch: &mut WriteBatch) -> Result<(), Error> {
+        let mut stats = batch.get_stats()?;
+        let mut terms: HashMap<&str, TermData> = HashMap::new();
+        let mut postings = Vec::new();
+        for doc in docs.iter() {
+            let doc_data = DocumentData {
+                path: doc.path.clone(),
+                length: doc.length,
+                path_length: doc.path_length,
+                size: doc.size,
+                modified: doc.modified,
+                content_hash: doc.content_hash,
+            };
+            batch.put_document(doc.id, &doc_data)?;
+            batch.put_path(&doc.path, doc.id)?;
+            if let Some(text) = doc.content.as_ref() {
+                batch.put_content(doc.id, text)?;
+            }
+            stats.add(&doc_data);
+
+            for (term, term_data) in doc.terms.iter() {
+                let total = terms.entry(term).or_default();
+                total.count += term_data.count;
+                total.document_count += term_data.document_count;
+            }
+            for (term, doc_term_data) in doc.doc_terms.iter() {
+                let locations = doc.locations.get(term);
+                postings.push((term.as_str(), doc.id, doc_term_data, locations));
+            }
+        }
+        batch.put_stats(stats)?;
+
+        // Terms that are only in the path have no locations.
+        let no_locations = TermLocations::default();
+        postings.sort_by_key(|(term, id, _, _)| (*term, *id));
+        for (term, id, doc_term_data, locations) in postings {
+            batch.put_posting(term, id, doc_term_data)?;
+            batch.put_document_term(id, term, locations.unwrap_or(&no_locations))?;
         }
 
-        for (term, doc_term_data) in self.doc_terms.iter() {
-            index.posting_lists().put(term, self.id, doc_term_data)?;
+        let mut terms: Vec<(&str, TermData)> = terms.into_iter().collect();
+        terms.sort_by_key(|(term, _)| *term);
+        for (term, term_data) in terms {
+            batch.merge_term(term, &term_data)?;
         }
 
         Ok(())
@@ -85,21 +328,560 @@ impl TokenProcessor for DocProcessor {
         // TODO: Deal with partial matches.
         let mut term_data = self.terms.remove(token.token).unwrap_or_default();
         let mut doc_term_data = self.doc_terms.remove(token.token).unwrap_or_default();
+        // These are just for this document until they're merged into the index.
         term_data.count += 1;
         term_data.document_count = 1;
-        if self.in_path {
-            doc_term_data.path_count += 1;
+        let position = token.occurrence.position as u64;
+        let (count, positions) = if self.in_path {
+            (
+                &mut doc_term_data.path_count,
+                &mut doc_term_data.path_positions,
+            )
         } else {
-            doc_term_data.body_count += 1;
+            (
+                &mut doc_term_data.body_count,
+                &mut doc_term_data.body_positions,
+            )
+        };
+        *count += 1;
+        // A word can contain the same partial token more than once.
+        if positions.last() != Some(&position) {
+            positions.push(position);
         }
         self.terms.insert(token.token.to_string(), term_data);
         self.doc_terms
             .insert(token.token.to_string(), doc_term_data);
 
-        self.length = self.length.max((token.occurrence.position + 1) as u64);
+        if !self.in_path {
+            self.add_location(token);
+        }
+
+        // Each place in the text is counted once, even if filters added more terms there,
+        // like synonyms. A term is only at each place once, so its count is never more than
+        // the length.
+        let counted = Some((self.in_path, token.occurrence));
+        if self.last_counted != counted {
+            self.last_counted = counted;
+            if self.in_path {
+                self.path_length += 1;
+            } else {
+                self.length += 1;
+            }
+        }
+    }
+}
+
+impl DocProcessor {
+    fn add_location(&mut self, token: &TokenSlice) {
+        let occurrence = &token.occurrence;
+        let location = Location {
+            line: occurrence.line as u64,
+            column: occurrence.column as u64,
+            offset: occurrence.offset as u64,
+            length: occurrence.length as u64,
+        };
+        if let Some(locations) = self.locations.get_mut(token.token) {
+            locations.push(location);
+        } else {
+            let mut locations = TermLocations::default();
+            locations.push(location);
+            self.locations.insert(token.token.to_string(), locations);
+        }
+    }
+}
+
+type PostingIterator<'a> =
+    Box<dyn Iterator<Item = Result<(DocumentId, DocumentTermData), Error>> + 'a>;
+
+// A position in one term's posting list, which can skip ahead to a given document.
+// If field is set, documents that only have the term in the other field are skipped.
+struct PostingCursor<'a> {
+    store: &'a PostingListStore,
+    term: &'a str,
+    field: Option<Field>,
+    postings: PostingIterator<'a>,
+    current: Option<(DocumentId, DocumentTermData)>,
+}
+
+impl<'a> PostingCursor<'a> {
+    fn new(
+        store: &'a PostingListStore,
+        term: &'a str,
+        field: Option<Field>,
+    ) -> Result<Self, Error> {
+        let mut cursor = PostingCursor {
+            store,
+            term,
+            field,
+            postings: Box::new(store.get(term)),
+            current: None,
+        };
+        cursor.next()?;
+        Ok(cursor)
+    }
+
+    fn data(&self) -> Option<&DocumentTermData> {
+        self.current.as_ref().map(|(_, data)| data)
+    }
+
+    fn next(&mut self) -> Result<(), Error> {
+        loop {
+            self.current = self.postings.next().transpose()?;
+            let in_field = match (&self.current, self.field) {
+                (Some((_, data)), Some(Field::Body)) => data.body_count > 0,
+                (Some((_, data)), Some(Field::Path)) => data.path_count > 0,
+                _ => true,
+            };
+            if in_field {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Something that walks through documents in id order.
+trait DocCursor {
+    // The current document, or None if there are no more.
+    fn doc(&self) -> Option<DocumentId>;
+
+    // Moves to the first document with an id >= target.
+    fn seek(&mut self, target: DocumentId) -> Result<(), Error>;
+}
+
+impl DocCursor for PostingCursor<'_> {
+    fn doc(&self) -> Option<DocumentId> {
+        self.current.as_ref().map(|(id, _)| *id)
+    }
+
+    fn seek(&mut self, target: DocumentId) -> Result<(), Error> {
+        match self.doc() {
+            Some(id) if id < target => {
+                self.postings = Box::new(self.store.get_from(self.term, target));
+                self.next()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Leapfrogs the cursors until they are all on the same document with an id >= target,
+// and returns it.
+fn intersect(
+    cursors: &mut [impl DocCursor],
+    mut target: DocumentId,
+) -> Result<Option<DocumentId>, Error> {
+    if cursors.is_empty() {
+        return Ok(None);
+    }
+    loop {
+        for cursor in cursors.iter_mut() {
+            cursor.seek(target)?;
+            let Some(id) = cursor.doc() else {
+                return Ok(None);
+            };
+            target = target.max(id);
+        }
+
+        let mut aligned = true;
+        for cursor in cursors.iter_mut() {
+            cursor.seek(target)?;
+            match cursor.doc() {
+                None => return Ok(None),
+                Some(id) if id != target => aligned = false,
+                Some(_) => {}
+            }
+        }
+        if aligned {
+            return Ok(Some(target));
+        }
+    }
+}
+
+// Whether there's a position in the first list where each list has a position offsets[i]
+// after it.
+fn contains_phrase(positions: &[&[u64]], offsets: &[u64]) -> bool {
+    let Some((first, rest)) = positions.split_first() else {
+        return false;
+    };
+    first.iter().any(|start| {
+        rest.iter()
+            .zip(&offsets[1..])
+            .all(|(list, offset)| list.binary_search(&(start + offset)).is_ok())
+    })
+}
+
+// The number of words in the smallest span with a position from each list.
+// If ordered is true, the positions must also be in the same order as the lists.
+fn min_window(positions: &[&[u64]], ordered: bool) -> Option<u64> {
+    if positions.is_empty() {
+        return None;
+    }
+    if ordered {
+        min_ordered_window(positions)
+    } else {
+        min_unordered_window(positions)
+    }
+}
+
+fn min_ordered_window(positions: &[&[u64]]) -> Option<u64> {
+    let (first, rest) = positions.split_first()?;
+    let mut best: Option<u64> = None;
+    for start in first.iter() {
+        // For a fixed start, taking the next position after the previous term's
+        // is always the tightest choice.
+        let mut last = *start;
+        for list in rest.iter() {
+            let next = list.partition_point(|p| *p <= last);
+            let Some(p) = list.get(next) else {
+                // Any later start won't be able to finish either.
+                return best;
+            };
+            last = *p;
+        }
+        let window = last - start + 1;
+        best = Some(best.map_or(window, |b| b.min(window)));
+    }
+    best
+}
+
+fn min_unordered_window(positions: &[&[u64]]) -> Option<u64> {
+    let mut all: Vec<(u64, usize)> = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(i, list)| list.iter().map(move |p| (*p, i)))
+        .collect();
+    all.sort();
+
+    // Slide a window over the merged positions, shrinking it from the left
+    // whenever it contains every list.
+    let mut counts = vec![0usize; positions.len()];
+    let mut covered = 0;
+    let mut start = 0;
+    let mut best: Option<u64> = None;
+    for &(end_position, i) in all.iter() {
+        if counts[i] == 0 {
+            covered += 1;
+        }
+        counts[i] += 1;
+        while covered == positions.len() {
+            let (start_position, j) = all[start];
+            let window = end_position - start_position + 1;
+            best = Some(best.map_or(window, |b| b.min(window)));
+            counts[j] -= 1;
+            if counts[j] == 0 {
+                covered -= 1;
+            }
+            start += 1;
+        }
+    }
+    best
+}
+
+// The body and path position lists for each term.
+fn positions<'a>(doc_term_data: &[&'a DocumentTermData]) -> (Vec<&'a [u64]>, Vec<&'a [u64]>) {
+    let body = doc_term_data
+        .iter()
+        .map(|data| &data.body_positions[..])
+        .collect();
+    let path = doc_term_data
+        .iter()
+        .map(|data| &data.path_positions[..])
+        .collect();
+    (body, path)
+}
+
+// Walks through the documents that match a query, in id order.
+enum Matcher<'a> {
+    Term(PostingCursor<'a>),
+    Positional {
+        cursors: Vec<PostingCursor<'a>>,
+        offsets: &'a [u64], // where each term is relative to the first, for a phrase
+        field: Option<Field>,
+        near: Option<(u64, bool)>, // the distance and order for a proximity match
+        current: Option<DocumentId>,
+        window: Option<u64>,
+    },
+    Boolean {
+        required: Vec<Matcher<'a>>,
+        optional: Vec<Matcher<'a>>,
+        excluded: Vec<Matcher<'a>>,
+        current: Option<DocumentId>,
+    },
+}
+
+impl<'a> Matcher<'a> {
+    // If field is set, only that part of each document is matched.
+    fn new(
+        store: &'a PostingListStore,
+        query: &'a Query,
+        field: Option<Field>,
+    ) -> Result<Self, Error> {
+        let mut matcher = match query {
+            Query::Term(term) => Matcher::Term(PostingCursor::new(store, term, field)?),
+            Query::Phrase { terms, offsets } => {
+                Matcher::positional(store, terms, offsets, field, None)?
+            }
+            Query::Near {
+                terms,
+                distance,
+                ordered,
+            } => Matcher::positional(store, terms, &[], field, Some((*distance, *ordered)))?,
+            Query::Boolean {
+                required,
+                optional,
+                excluded,
+            } => Matcher::Boolean {
+                required: Matcher::all(store, required, field)?,
+                optional: Matcher::all(store, optional, field)?,
+                excluded: Matcher::all(store, excluded, field)?,
+                current: None,
+            },
+            Query::InField { field, query } => return Matcher::new(store, query, Some(*field)),
+        };
+        matcher.find(0)?;
+        Ok(matcher)
+    }
+
+    fn positional(
+        store: &'a PostingListStore,
+        terms: &'a [String],
+        offsets: &'a [u64],
+        field: Option<Field>,
+        near: Option<(u64, bool)>,
+    ) -> Result<Self, Error> {
+        let cursors: Result<Vec<PostingCursor>, Error> = terms
+            .iter()
+            .map(|term| PostingCursor::new(store, term, field))
+            .collect();
+        Ok(Matcher::Positional {
+            cursors: cursors?,
+            offsets,
+            field,
+            near,
+            current: None,
+            window: None,
+        })
+    }
+
+    fn all(
+        store: &'a PostingListStore,
+        queries: &'a [Query],
+        field: Option<Field>,
+    ) -> Result<Vec<Self>, Error> {
+        queries
+            .iter()
+            .map(|query| Matcher::new(store, query, field))
+            .collect()
+    }
+
+    // Moves to the first matching document with an id >= target.
+    fn find(&mut self, mut target: DocumentId) -> Result<(), Error> {
+        match self {
+            Matcher::Term(cursor) => cursor.seek(target),
+            Matcher::Positional {
+                cursors,
+                offsets,
+                field,
+                near,
+                current,
+                window,
+            } => {
+                *current = None;
+                *window = None;
+                while let Some(doc) = intersect(cursors, target)? {
+                    let data: Vec<&DocumentTermData> =
+                        cursors.iter().filter_map(|cursor| cursor.data()).collect();
+                    let (mut body, mut path) = positions(&data);
+                    match field {
+                        Some(Field::Body) => path.clear(),
+                        Some(Field::Path) => body.clear(),
+                        None => {}
+                    }
+                    match near {
+                        None => {
+                            if contains_phrase(&body, offsets) || contains_phrase(&path, offsets) {
+                                *current = Some(doc);
+                                return Ok(());
+                            }
+                        }
+                        Some((distance, ordered)) => {
+                            let best = [min_window(&body, *ordered), min_window(&path, *ordered)]
+                                .into_iter()
+                                .flatten()
+                                .min();
+                            // The distance is the number of other words between the terms.
+                            if let Some(best) = best
+                                && best.saturating_sub(cursors.len() as u64) <= *distance
+                            {
+                                *current = Some(doc);
+                                *window = Some(best);
+                                return Ok(());
+                            }
+                        }
+                    }
+                    let Some(next) = doc.checked_add(1) else {
+                        break;
+                    };
+                    target = next;
+                }
+                Ok(())
+            }
+            Matcher::Boolean {
+                required,
+                optional,
+                excluded,
+                current,
+            } => {
+                *current = None;
+                loop {
+                    let candidate = if required.is_empty() {
+                        let mut lowest: Option<DocumentId> = None;
+                        for matcher in optional.iter_mut() {
+                            matcher.seek(target)?;
+                            if let Some(id) = matcher.doc() {
+                                lowest = Some(lowest.map_or(id, |lowest| lowest.min(id)));
+                            }
+                        }
+                        lowest
+                    } else {
+                        intersect(required, target)?
+                    };
+                    let Some(doc) = candidate else {
+                        return Ok(());
+                    };
+
+                    let mut is_excluded = false;
+                    for matcher in excluded.iter_mut() {
+                        matcher.seek(doc)?;
+                        is_excluded |= matcher.doc() == Some(doc);
+                    }
+                    if !is_excluded {
+                        // Line up the optional clauses so they can be scored.
+                        for matcher in optional.iter_mut() {
+                            matcher.seek(doc)?;
+                        }
+                        *current = Some(doc);
+                        return Ok(());
+                    }
+
+                    let Some(next) = doc.checked_add(1) else {
+                        return Ok(());
+                    };
+                    target = next;
+                }
+            }
+        }
+    }
+
+    // Adds the data for every term that matched the current document.
+    fn collect(&self, doc: DocumentId, matched: &mut HashMap<&'a str, DocumentTermData>) {
+        if self.doc() != Some(doc) {
+            return;
+        }
+        match self {
+            Matcher::Term(cursor) => {
+                if let Some(data) = cursor.data() {
+                    matched.insert(cursor.term, data.clone());
+                }
+            }
+            Matcher::Positional { cursors, .. } => {
+                for cursor in cursors.iter() {
+                    if let Some(data) = cursor.data() {
+                        matched.insert(cursor.term, data.clone());
+                    }
+                }
+            }
+            Matcher::Boolean {
+                required, optional, ..
+            } => {
+                for matcher in required.iter().chain(optional.iter()) {
+                    matcher.collect(doc, matched);
+                }
+            }
+        }
+    }
+
+    // The smallest window of any proximity match in the current document.
+    fn window(&self, doc: DocumentId) -> Option<u64> {
+        if self.doc() != Some(doc) {
+            return None;
+        }
+        match self {
+            Matcher::Term(_) => None,
+            Matcher::Positional { window, .. } => *window,
+            Matcher::Boolean {
+                required, optional, ..
+            } => required
+                .iter()
+                .chain(optional.iter())
+                .filter_map(|matcher| matcher.window(doc))
+                .min(),
+        }
+    }
+}
+
+impl DocCursor for Matcher<'_> {
+    fn doc(&self) -> Option<DocumentId> {
+        match self {
+            Matcher::Term(cursor) => cursor.doc(),
+            Matcher::Positional { current, .. } => *current,
+            Matcher::Boolean { current, .. } => *current,
+        }
+    }
+
+    fn seek(&mut self, target: DocumentId) -> Result<(), Error> {
+        match self.doc() {
+            Some(id) if id < target => self.find(target),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The best scoring documents seen so far.
+struct TopDocs {
+    queue: PriorityQueue<DocumentId, OrderedFloat<f32>>,
+    results: HashMap<DocumentId, SearchResult>, // the result for each id in the queue
+    max_docs: i32,
+}
+
+impl TopDocs {
+    fn new(max_docs: i32) -> Self {
+        TopDocs {
+            queue: PriorityQueue::new(),
+            results: HashMap::new(),
+            max_docs,
+        }
+    }
+
+    fn push(&mut self, result: SearchResult) {
+        self.queue.push(result.id, OrderedFloat(-result.score));
+        self.results.insert(result.id, result);
+        if self.queue.len() as i32 > self.max_docs
+            && let Some((id, _)) = self.queue.pop()
+        {
+            self.results.remove(&id);
+        }
+    }
+
+    // Returns the results from highest to lowest score.
+    fn into_results(mut self) -> Vec<SearchResult> {
+        let ids: Vec<(u128, OrderedFloat<f32>)> = self.queue.into_sorted_iter().collect();
+        ids.into_iter()
+            .rev()
+            .filter_map(|(id, _score)| self.results.remove(&id))
+            .collect()
     }
 }
 
+// Settings for how documents are added to an index.
+#[derive(Debug, Default, Clone)]
+pub struct IndexOptions {
+    // Whether to store the text of each document, for snippets. Once an index stores
+    // content, it keeps storing it, even if it's opened without this.
+    pub store_content: bool,
+    pub analyzer: Arc<Analyzer>, // turns documents and query strings into terms
+}
+
 // An inverted index is a map of string token to posting list.
 // The token is the word being looked up, and the posting list
 // is a list of all the documents that the word is in, sorted
@@ -107,6 +889,12 @@ impl TokenProcessor for DocProcessor {
 // the word, such as where in the document the word appeared.
 pub struct InvertedIndex {
     store: IndexStore,
+    ids: Box<dyn IdAllocator>,
+    options: IndexOptions,
+    // Held while changing the index, so that ids and stats aren't updated concurrently.
+    writer: Mutex<()>,
+    // The thread with an open IndexWriter, which holds the writer lock until it's dropped.
+    writer_thread: Mutex<Option<ThreadId>>,
 }
 
 pub trait Scorer {
@@ -117,13 +905,81 @@ pub trait Scorer {
         terms: &[String],
         term_data: &[TermData],
         doc_term_data: &[DocumentTermData],
+        stats: &CollectionStats,
     ) -> f32;
+
+    // Scores a doc matched by a proximity search, where window is the number of words
+    // in the smallest span of the doc containing all of the terms.
+    // By default, docs where the terms are closer together score higher.
+    #[allow(clippy::too_many_arguments)]
+    fn score_window(
+        &self,
+        doc_id: DocumentId,
+        doc_data: &DocumentData,
+        terms: &[String],
+        term_data: &[TermData],
+        doc_term_data: &[DocumentTermData],
+        stats: &CollectionStats,
+        window: u64,
+    ) -> f32 {
+        self.score(doc_id, doc_data, terms, term_data, doc_term_data, stats) - (window as f32).ln()
+    }
 }
 
 impl InvertedIndex {
     pub fn new(keyspace: &Keyspace) -> Result<Self, Error> {
+        Self::with_id_allocator(keyspace, SequentialIds {})
+    }
+
+    // Opens the index, using ids to choose the id of each new document.
+    pub fn with_id_allocator(
+        keyspace: &Keyspace,
+        ids: impl IdAllocator + 'static,
+    ) -> Result<Self, Error> {
+        Self::open(keyspace, Box::new(ids), IndexOptions::default())
+    }
+
+    // Opens the index, with options for how documents are indexed.
+    // Returns an AnalyzerMismatch error if the index has documents that were analyzed
+    // differently, since searches with this analyzer wouldn't find their terms.
+    pub fn with_options(keyspace: &Keyspace, options: IndexOptions) -> Result<Self, Error> {
+        Self::open(keyspace, Box::new(SequentialIds {}), options)
+    }
+
+    fn open(
+        keyspace: &Keyspace,
+        ids: Box<dyn IdAllocator>,
+        mut options: IndexOptions,
+    ) -> Result<Self, Error> {
         let store = IndexStore::new(keyspace)?;
-        Ok(InvertedIndex { store })
+        let saved = store.stats().get_settings()?;
+        let mut settings = saved.clone();
+
+        // An empty index can switch to any analyzer. Indexes from before the analyzer was
+        // saved always used the default one.
+        let analyzer = options.analyzer.id();
+        if store.stats().get()?.document_count == 0 {
+            settings.analyzer = analyzer.clone();
+        } else if settings.analyzer.is_empty() {
+            settings.analyzer = Analyzer::default().id();
+        }
+        if settings.analyzer != analyzer {
+            return Err(Error::AnalyzerMismatch {});
+        }
+
+        settings.store_content |= options.store_content;
+        options.store_content = settings.store_content;
+        if settings != saved {
+            store.stats().put_settings(&settings)?;
+        }
+
+        Ok(InvertedIndex {
+            store,
+            ids,
+            options,
+            writer: Mutex::new(()),
+            writer_thread: Mutex::new(None),
+        })
     }
 
     fn terms(&self) -> &TermStore {
@@ -138,117 +994,426 @@ impl InvertedIndex {
         self.store.posting_lists()
     }
 
-    fn new_document_id(&self) -> Result<DocumentId, Error> {
-        self.store.documents().new_id()
+    pub(crate) fn batch(&self) -> WriteBatch<'_> {
+        self.store.batch()
+    }
+
+    // Takes the writer lock. If an IndexWriter on this thread already has it, waiting
+    // would never finish, so this returns an error instead.
+    pub(crate) fn lock_writer(&self) -> Result<MutexGuard<'_, ()>, Error> {
+        if *self.writer_thread.lock().unwrap() == Some(thread::current().id()) {
+            return Err(Error::WriterOpen {});
+        }
+        Ok(self.writer.lock().unwrap())
+    }
+
+    // Takes the writer lock for an IndexWriter, until close_writer is called.
+    pub(crate) fn open_writer(&self) -> Result<MutexGuard<'_, ()>, Error> {
+        let guard = self.lock_writer()?;
+        *self.writer_thread.lock().unwrap() = Some(thread::current().id());
+        Ok(guard)
+    }
+
+    // Called by an IndexWriter just before it gives up the writer lock.
+    pub(crate) fn close_writer(&self) {
+        *self.writer_thread.lock().unwrap() = None;
+    }
+
+    // Tokenizes a document, without touching the index.
+    // Text that isn't valid UTF-8 is decoded lossily, but the size and hash are of the
+    // bytes as they were read, so they can still be compared with the file.
+    pub(crate) fn process_document(
+        &self,
+        path: &str,
+        doc: &mut impl std::io::Read,
+    ) -> Result<DocProcessor, Error> {
+        let mut processor = DocProcessor::new(0, path);
+        let mut bytes = Vec::new();
+        processor.in_path = true;
+        self.options.analyzer.analyze(path, &mut processor);
+        processor.in_path = false;
+        doc.read_to_end(&mut bytes)?;
+        let text = String::from_utf8_lossy(&bytes);
+        self.options.analyzer.analyze(&text, &mut processor);
+        processor.size = bytes.len() as u64;
+        processor.content_hash = content_hash(&bytes);
+        if self.options.store_content {
+            processor.content = Some(text.into_owned());
+        }
+        Ok(processor)
     }
 
     // Add a document to the index.
-    // If the document is already in the index, this will add it a second itme.
+    // If the document is already in the index, this will add it a second time,
+    // and lookup_path will return the new one. Use upsert_document to replace it instead.
     pub fn add_document(
         &self,
         path: &str,
         doc: &mut impl std::io::Read,
     ) -> Result<DocumentId, Error> {
-        let id = self.new_document_id()?;
-        let mut processor = DocProcessor::new(id, path);
-        let mut text = String::new();
-        processor.in_path = true;
-        parse_text(path, &mut processor);
-        processor.in_path = false;
-        doc.read_to_string(&mut text)?;
-        parse_text(&text, &mut processor);
-        processor.finalize(&self.store)?;
-        Ok(id)
+        let ids = self.add_documents([(path, doc)])?;
+        Ok(ids[0])
     }
 
-    // Search for docs that have any of the words.
-    pub fn search(
+    // Add several documents to the index, returning their ids in the same order.
+    // Either all of the documents are added, or none of them are.
+    pub fn add_documents<'p, R: std::io::Read>(
         &self,
-        terms: &[String],
-        scorer: impl Scorer,
-        max_docs: i32,
+        docs: impl IntoIterator<Item = (&'p str, R)>,
     ) -> Result<Vec<DocumentId>, Error> {
-        // Look up the data for each term.
-        let term_data: Result<Vec<Option<TermData>>, Error> =
-            terms.iter().map(|term| self.terms().get(term)).collect();
-        let term_data = term_data?;
-        let term_data: Vec<TermData> = term_data
-            .into_iter()
-            .map(|item| item.unwrap_or(TermData::default()))
-            .collect();
+        let mut processors = Vec::new();
+        for (path, mut doc) in docs {
+            processors.push(self.process_document(path, &mut doc)?);
+        }
 
-        // Look up all the posting lists.
-        let mut postings: Vec<_> = terms
-            .iter()
-            .map(|term| self.postings().get(term).peekable())
+        let _writer = self.lock_writer()?;
+        let mut batch = self.store.batch();
+        let mut ids = Vec::new();
+        for processor in processors.iter_mut() {
+            ids.push(self.allocate_id(&mut batch, processor)?);
+        }
+        self.write_documents(&mut batch, processors)?;
+        batch.commit()?;
+        Ok(ids)
+    }
+
+    // Add a document to the index, replacing any document already in the index with the
+    // same path. The old document's postings and term counts are removed in the same
+    // write, so searches see either the old document or the new one.
+    pub fn upsert_document(
+        &self,
+        path: &str,
+        doc: &mut impl std::io::Read,
+    ) -> Result<DocumentId, Error> {
+        self.upsert_file(path, doc, 0)
+    }
+
+    // Like upsert_document, but also records when the file was last modified, in
+    // nanoseconds since the epoch, so that it can be checked for changes later.
+    pub fn upsert_file(
+        &self,
+        path: &str,
+        doc: &mut impl std::io::Read,
+        modified: u64,
+    ) -> Result<DocumentId, Error> {
+        let mut processor = self.process_document(path, doc)?;
+        processor.modified = modified;
+        processor.replace = true;
+
+        let _writer = self.lock_writer()?;
+        let mut batch = self.store.batch();
+        let id = self.allocate_id(&mut batch, &mut processor)?;
+        self.write_documents(&mut batch, vec![processor])?;
+        batch.commit()?;
+        Ok(id)
+    }
+
+    // Chooses the id of a tokenized document. Requires the writer lock.
+    pub(crate) fn allocate_id(
+        &self,
+        batch: &mut WriteBatch,
+        processor: &mut DocProcessor,
+    ) -> Result<DocumentId, Error> {
+        processor.id = self.ids.allocate(batch, &processor.new_document())?;
+        Ok(processor.id)
+    }
+
+    // Adds tokenized documents, which already have ids, to the batch. A document marked
+    // to replace others removes the document with the same path. If that document is
+    // earlier in processors, it's never written at all. Requires the writer lock.
+    pub(crate) fn write_documents(
+        &self,
+        batch: &mut WriteBatch,
+        processors: Vec<DocProcessor>,
+    ) -> Result<(), Error> {
+        // Documents are removed before any are written, so every removed document was
+        // committed before this batch.
+        let mut latest = HashMap::new();
+        let mut replaced = HashSet::new();
+        for (i, processor) in processors.iter().enumerate() {
+            let previous = latest.insert(processor.path.as_str(), i);
+            if !processor.replace {
+                continue;
+            }
+            if let Some(previous) = previous {
+                replaced.insert(previous);
+            } else if let Some(old) = batch.get_path(&processor.path)? {
+                self.remove_document(batch, old)?;
+            }
+        }
+        let processors: Vec<DocProcessor> = processors
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, processor)| (!replaced.contains(&i)).then_some(processor))
             .collect();
+        DocProcessor::finalize(&processors, batch)
+    }
+
+    // Changes when a document was last modified, for a file that was touched but has the
+    // same contents. Returns false if there was no document with that id.
+    pub fn set_modified(&self, id: DocumentId, modified: u64) -> Result<bool, Error> {
+        let _writer = self.lock_writer()?;
+        let Some(mut doc_data) = self.docs().get(id)? else {
+            return Ok(false);
+        };
+        doc_data.modified = modified;
+        let mut batch = self.store.batch();
+        batch.put_document(id, &doc_data)?;
+        batch.commit()?;
+        Ok(true)
+    }
 
-        let mut top_docs = PriorityQueue::new();
+    // The data for a document, if there is a document with that id.
+    pub fn document(&self, id: DocumentId) -> Result<Option<DocumentData>, Error> {
+        self.docs().get(id)
+    }
 
-        loop {
-            // Find the lowest id doc to score.
-            let mut first_doc: Option<u128> = None;
-            for posting in postings.iter_mut() {
-                if let Some(result) = posting.peek() {
-                    match result {
-                        Ok((id, _data)) => {
-                            if let Some(lowest_id) = first_doc {
-                                if *id < lowest_id {
-                                    first_doc = Some(*id)
-                                }
-                            } else {
-                                first_doc = Some(*id)
-                            }
-                        }
-                        Err(_) => return Err(posting.next().unwrap().unwrap_err()),
-                    }
-                }
+    // Returns every document in the index, in id order.
+    pub fn documents(&self) -> impl Iterator<Item = Result<(DocumentId, DocumentData), Error>> {
+        self.docs().iter()
+    }
+
+    // The id of the document with the given path, if there is one.
+    pub fn lookup_path(&self, path: &str) -> Result<Option<DocumentId>, Error> {
+        self.store.paths().get(path)
+    }
+
+    // Returns every document path that starts with prefix, along with its id, in path
+    // order. Pass an empty prefix for every path.
+    pub fn paths(&self, prefix: &str) -> impl Iterator<Item = Result<(String, DocumentId), Error>> {
+        self.store.paths().prefix(prefix)
+    }
+
+    // Removes a document from the index, so that it won't show up in any more searches.
+    // Returns false if there was no document with that id.
+    pub fn delete_document(&self, id: DocumentId) -> Result<bool, Error> {
+        let _writer = self.lock_writer()?;
+        let mut batch = self.store.batch();
+        let deleted = self.remove_document(&mut batch, id)?;
+        batch.commit()?;
+        Ok(deleted)
+    }
+
+    // Adds removing a document's postings, term counts, and stats to the batch.
+    // The document has to have been committed before the batch, since its postings are
+    // read from the index. Requires the writer lock.
+    pub(crate) fn remove_document(
+        &self,
+        batch: &mut WriteBatch,
+        id: DocumentId,
+    ) -> Result<bool, Error> {
+        let Some(doc_data) = batch.get_document(id)? else {
+            return Ok(false);
+        };
+        batch.remove_document(id)?;
+        batch.remove_content(id)?;
+        if batch.get_path(&doc_data.path)? == Some(id) {
+            batch.remove_path(&doc_data.path)?;
+        }
+
+        let terms: Result<Vec<String>, Error> = self.store.document_terms().get(id).collect();
+        for term in terms? {
+            if let Some(doc_term_data) = self.postings().get_one(&term, id)? {
+                let mut term_data = batch.get_term(&term)?.unwrap_or_default();
+                term_data.count = term_data
+                    .count
+                    .saturating_sub(doc_term_data.body_count + doc_term_data.path_count);
+                term_data.document_count = term_data.document_count.saturating_sub(1);
+                batch.put_term(&term, term_data)?;
+                batch.remove_posting(&term, id)?;
             }
-            let Some(first_doc) = first_doc else {
-                break;
-            };
+            batch.remove_document_term(id, &term)?;
+        }
 
-            // Grab the data for that doc.
-            let doc_data = self
-                .docs()
-                .get(first_doc)?
-                .unwrap_or(DocumentData::default());
-
-            // Grab the data for each term in this doc.
-            let mut doc_term_data: Vec<DocumentTermData> = Vec::new();
-            for posting in postings.iter_mut() {
-                if let Some(result) = posting.peek() {
-                    match result {
-                        Ok((id, _data)) => {
-                            if *id == first_doc {
-                                let (_, data) = posting.next().unwrap().unwrap();
-                                doc_term_data.push(data);
-                            } else {
-                                doc_term_data.push(DocumentTermData::default());
-                            }
-                        }
-                        Err(_) => return Err(posting.next().unwrap().unwrap_err()),
-                    }
+        let mut stats = batch.get_stats()?;
+        stats.remove(&doc_data);
+        batch.put_stats(stats)?;
+        Ok(true)
+    }
+
+    // The original text of a document, if the index stores it.
+    pub fn content(&self, id: DocumentId) -> Result<Option<String>, Error> {
+        self.store.content().get(id)
+    }
+
+    // Look up the data for each term, using defaults for terms not in the index.
+    fn term_data(&self, terms: &[String]) -> Result<Vec<TermData>, Error> {
+        let term_data: Result<Vec<Option<TermData>>, Error> =
+            terms.iter().map(|term| self.terms().get(term)).collect();
+        let term_data = term_data?;
+        Ok(term_data
+            .into_iter()
+            .map(|item| item.unwrap_or(TermData::default()))
+            .collect())
+    }
+
+    // Statistics about all of the documents in the index.
+    pub fn collection_stats(&self) -> Result<CollectionStats, Error> {
+        self.store.stats().get()
+    }
+
+    // The terms in the index that start with prefix, up to MAX_EXPANSIONS of them.
+    pub fn expand_prefix(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        self.terms()
+            .prefix(prefix)
+            .take(MAX_EXPANSIONS)
+            .map(|result| result.map(|(term, _)| term))
+            .collect()
+    }
+
+    // The terms in the index within distance edits of term, up to MAX_EXPANSIONS of them.
+    // This has to look at every term in the index.
+    pub fn expand_fuzzy(&self, term: &str, distance: u32) -> Result<Vec<String>, Error> {
+        let distance = distance as usize;
+        let length = term.chars().count();
+        let mut terms = Vec::new();
+        for result in self.terms().prefix("") {
+            let (candidate, _) = result?;
+            if candidate.chars().count().abs_diff(length) > distance {
+                continue;
+            }
+            if edit_distance(term, &candidate) <= distance {
+                terms.push(candidate);
+                if terms.len() >= MAX_EXPANSIONS {
+                    break;
                 }
             }
+        }
+        Ok(terms)
+    }
+
+    // Search for docs that have any of the words.
+    pub fn search(
+        &self,
+        terms: &[String],
+        scorer: impl Scorer,
+        max_docs: i32,
+    ) -> Result<Vec<SearchResult>, Error> {
+        self.search_query(&Query::any(terms), scorer, max_docs)
+    }
+
+    // Search for docs that have all of the words next to each other, in order,
+    // either in the body or in the path.
+    pub fn search_phrase(
+        &self,
+        terms: &[String],
+        scorer: impl Scorer,
+        max_docs: i32,
+    ) -> Result<Vec<SearchResult>, Error> {
+        self.search_query(&Query::phrase(terms), scorer, max_docs)
+    }
+
+    // Search for docs that have all of the words with at most distance other words between
+    // them, either in the body or in the path. If ordered is true, the words must also appear in
+    // the same order as in terms.
+    pub fn search_near(
+        &self,
+        terms: &[String],
+        distance: u64,
+        ordered: bool,
+        scorer: impl Scorer,
+        max_docs: i32,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let query = Query::Near {
+            terms: terms.to_vec(),
+            distance,
+            ordered,
+        };
+        self.search_query(&query, scorer, max_docs)
+    }
+
+    // Search for docs that match a query string, as described in parse_query. The words in
+    // the query are analyzed the same way as the documents were.
+    pub fn search_string(
+        &self,
+        query: &str,
+        scorer: impl Scorer,
+        max_docs: i32,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let query = parse_query_with(query, &self.options.analyzer)?.to_query(self)?;
+        self.search_query(&query, scorer, max_docs)
+    }
 
-            let score = scorer.score(first_doc, &doc_data, &terms, &term_data, &doc_term_data);
-            top_docs.push(first_doc, OrderedFloat(-score));
-            if top_docs.len() as i32 > max_docs {
-                top_docs.pop();
+    // Returns every occurrence of the terms in the body of a document, sorted by location.
+    pub fn matches(&self, id: DocumentId, terms: &[String]) -> Result<Vec<Match>, Error> {
+        let mut matches = Vec::new();
+        for term in terms {
+            if let Some(locations) = self.store.document_terms().get_locations(id, term)? {
+                matches.extend(locations.iter().map(|location| Match {
+                    term: term.clone(),
+                    location,
+                }));
             }
         }
+        matches.sort_by_key(|m| m.location);
+        Ok(matches)
+    }
 
-        let results: Vec<(u128, OrderedFloat<f32>)> = top_docs.into_sorted_iter().collect();
-        /*
-        println!("results:");
-        for (id, score) in results.iter().rev() {
-            println!("  doc: {}, score: {}", *id, *score);
+    // Search for docs that match the query.
+    // The scorer is given every term in the query that isn't excluded.
+    pub fn search_query(
+        &self,
+        query: &Query,
+        scorer: impl Scorer,
+        max_docs: i32,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let terms = query.terms();
+        let term_data = self.term_data(&terms)?;
+        let stats = self.collection_stats()?;
+
+        let mut matcher = Matcher::new(self.postings(), query, None)?;
+        let mut top_docs = TopDocs::new(max_docs);
+
+        while let Some(doc) = matcher.doc() {
+            // Docs that have been deleted are skipped.
+            if let Some(doc_data) = self.docs().get(doc)? {
+                // Grab the data for each term in this doc.
+                let mut matched = HashMap::new();
+                matcher.collect(doc, &mut matched);
+                let doc_term_data: Vec<DocumentTermData> = terms
+                    .iter()
+                    .map(|term| matched.remove(term.as_str()).unwrap_or_default())
+                    .collect();
+
+                let score = match matcher.window(doc) {
+                    Some(window) => scorer.score_window(
+                        doc,
+                        &doc_data,
+                        &terms,
+                        &term_data,
+                        &doc_term_data,
+                        &stats,
+                        window,
+                    ),
+                    None => {
+                        scorer.score(doc, &doc_data, &terms, &term_data, &doc_term_data, &stats)
+                    }
+                };
+                let matched_terms = terms
+                    .iter()
+                    .zip(doc_term_data.iter())
+                    .filter(|(_, data)| data.body_count + data.path_count > 0)
+                    .map(|(term, data)| MatchedTerm {
+                        term: term.clone(),
+                        body_count: data.body_count,
+                        path_count: data.path_count,
+                    })
+                    .collect();
+                top_docs.push(SearchResult {
+                    id: doc,
+                    score,
+                    document: doc_data,
+                    matched_terms,
+                });
+            }
+
+            let Some(next) = doc.checked_add(1) else {
+                break;
+            };
+            matcher.seek(next)?;
         }
-        */
-        let results = results.into_iter().rev().map(|(id, _score)| id).collect();
 
-        Ok(results)
+        Ok(top_docs.into_results())
     }
 }
 
@@ -257,8 +1422,19 @@ mod tests {
     use fjall::Config;
 
     use super::*;
+    use crate::analyzer::{
+        Language, LowercaseFilter, NormalizeFilter, StemFilter, StopwordFilter, SynonymFilter,
+    };
+    use crate::id::{ContentHashIds, RandomIds};
+    use crate::scorer::{
+        Bm25Scorer, Bm25fScorer, DirichletScorer, FieldWeight, JelinekMercerScorer,
+    };
     use std::{collections::HashMap, iter::zip};
 
+    fn ids(results: Vec<SearchResult>) -> Vec<DocumentId> {
+        results.into_iter().map(|result| result.id).collect()
+    }
+
     // A test scorer that verifies all the arguments thoroughly and then just sorts by document id.
     struct ArgumentVerifyingTestScorer {}
 
@@ -270,6 +1446,7 @@ mod tests {
             terms: &[String],
             term_data: &[TermData],
             doc_term_data: &[DocumentTermData],
+            _stats: &CollectionStats,
         ) -> f32 {
             assert_eq!(terms.len(), term_data.len());
             assert_eq!(term_data.len(), doc_term_data.len());
@@ -285,7 +1462,7 @@ mod tests {
                         assert_eq!(4, term_data.document_count);
                     }
                     _ => {
-                        assert!(false, "unknown term {}", term);
+                        panic!("unknown term {}", term);
                     }
                 }
             }
@@ -305,7 +1482,7 @@ mod tests {
                                 assert_eq!(6, doc_term_data.body_count);
                             }
                             _ => {
-                                assert!(false, "unknown term {}", term);
+                                panic!("unknown term {}", term);
                             }
                         }
                     }
@@ -324,17 +1501,17 @@ mod tests {
                                 assert_eq!(8, doc_term_data.body_count);
                             }
                             _ => {
-                                assert!(false, "unknown term {}", term);
+                                panic!("unknown term {}", term);
                             }
                         }
                     }
                 }
                 _ => {
-                    assert!(false, "unknown document {}", doc_id);
+                    panic!("unknown document {}", doc_id);
                 }
             }
 
-            return doc_id as f32;
+            doc_id as f32
         }
     }
 
@@ -374,6 +1551,7 @@ mod tests {
                 DocumentData {
                     path: "/one/hundred".to_string(),
                     length: 101,
+                    ..Default::default()
                 },
             ),
             (
@@ -381,6 +1559,7 @@ mod tests {
                 DocumentData {
                     path: "/two/hundred".to_string(),
                     length: 201,
+                    ..Default::default()
                 },
             ),
         ]
@@ -393,6 +1572,7 @@ mod tests {
                 DocumentTermData {
                     body_count: 5,
                     path_count: 15,
+                    ..Default::default()
                 },
             ),
             (
@@ -400,6 +1580,7 @@ mod tests {
                 DocumentTermData {
                     body_count: 6,
                     path_count: 16,
+                    ..Default::default()
                 },
             ),
             (
@@ -407,6 +1588,7 @@ mod tests {
                 DocumentTermData {
                     body_count: 7,
                     path_count: 17,
+                    ..Default::default()
                 },
             ),
             (
@@ -414,6 +1596,7 @@ mod tests {
                 DocumentTermData {
                     body_count: 8,
                     path_count: 18,
+                    ..Default::default()
                 },
             ),
         ]
@@ -423,21 +1606,21 @@ mod tests {
         let index = InvertedIndex::new(&keyspace)?;
 
         for (term, data) in terms.iter() {
-            index.terms().put(&term, data)?;
+            index.terms().put(term, data)?;
         }
         for (doc, data) in documents.iter() {
             index.docs().put(*doc, data)?;
         }
         for ((term, doc), data) in document_term_data.iter() {
-            index.postings().put(&term, *doc, data)?;
+            index.postings().put(term, *doc, data)?;
         }
 
         let scorer = ArgumentVerifyingTestScorer {};
         let query: Vec<String> = ["a", "b"].into_iter().map(|s| s.to_string()).collect();
-        let results = index.search(&query, scorer, 10)?;
+        let results = ids(index.search(&query, scorer, 10)?);
 
         assert_eq!(2, results.len());
-        assert_eq!(200, *results.get(0).unwrap());
+        assert_eq!(200, *results.first().unwrap());
         assert_eq!(100, *results.get(1).unwrap());
 
         Ok(())
@@ -454,6 +1637,7 @@ mod tests {
             _terms: &[String],
             _term_data: &[TermData],
             _doc_term_data: &[DocumentTermData],
+            _stats: &CollectionStats,
         ) -> f32 {
             doc_data.length as f32
         }
@@ -476,6 +1660,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/one".to_string(),
                     length: 5,
+                    ..Default::default()
                 },
             ),
             (
@@ -483,6 +1668,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/two".to_string(),
                     length: 2,
+                    ..Default::default()
                 },
             ),
             (
@@ -490,6 +1676,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/three".to_string(),
                     length: 4,
+                    ..Default::default()
                 },
             ),
             (
@@ -497,6 +1684,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/four".to_string(),
                     length: 3,
+                    ..Default::default()
                 },
             ),
             (
@@ -504,6 +1692,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/five".to_string(),
                     length: 1,
+                    ..Default::default()
                 },
             ),
             (
@@ -511,6 +1700,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/six".to_string(),
                     length: 6,
+                    ..Default::default()
                 },
             ),
         ]
@@ -541,21 +1731,21 @@ mod tests {
         let index = InvertedIndex::new(&keyspace)?;
 
         for (term, data) in terms.iter() {
-            index.terms().put(&term, data)?;
+            index.terms().put(term, data)?;
         }
         for (doc, data) in documents.iter() {
             index.docs().put(*doc, data)?;
         }
         for ((term, doc), data) in document_term_data.iter() {
-            index.postings().put(&term, *doc, data)?;
+            index.postings().put(term, *doc, data)?;
         }
 
         let scorer = SortingScorer {};
         let query: Vec<String> = ["a"].into_iter().map(|s| s.to_string()).collect();
-        let results = index.search(&query, scorer, 10)?;
+        let results = ids(index.search(&query, scorer, 10)?);
 
         assert_eq!(6, results.len());
-        assert_eq!(600, *results.get(0).unwrap());
+        assert_eq!(600, *results.first().unwrap());
         assert_eq!(100, *results.get(1).unwrap());
         assert_eq!(300, *results.get(2).unwrap());
         assert_eq!(400, *results.get(3).unwrap());
@@ -582,6 +1772,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/one".to_string(),
                     length: 5,
+                    ..Default::default()
                 },
             ),
             (
@@ -589,6 +1780,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/two".to_string(),
                     length: 2,
+                    ..Default::default()
                 },
             ),
             (
@@ -596,6 +1788,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/three".to_string(),
                     length: 4,
+                    ..Default::default()
                 },
             ),
             (
@@ -603,6 +1796,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/four".to_string(),
                     length: 3,
+                    ..Default::default()
                 },
             ),
             (
@@ -610,6 +1804,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/five".to_string(),
                     length: 1,
+                    ..Default::default()
                 },
             ),
             (
@@ -617,6 +1812,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/six".to_string(),
                     length: 6,
+                    ..Default::default()
                 },
             ),
         ]
@@ -647,21 +1843,21 @@ mod tests {
         let index = InvertedIndex::new(&keyspace)?;
 
         for (term, data) in terms.iter() {
-            index.terms().put(&term, data)?;
+            index.terms().put(term, data)?;
         }
         for (doc, data) in documents.iter() {
             index.docs().put(*doc, data)?;
         }
         for ((term, doc), data) in document_term_data.iter() {
-            index.postings().put(&term, *doc, data)?;
+            index.postings().put(term, *doc, data)?;
         }
 
         let scorer = SortingScorer {};
         let query: Vec<String> = ["a"].into_iter().map(|s| s.to_string()).collect();
-        let results = index.search(&query, scorer, 3)?;
+        let results = ids(index.search(&query, scorer, 3)?);
 
         assert_eq!(3, results.len());
-        assert_eq!(600, *results.get(0).unwrap());
+        assert_eq!(600, *results.first().unwrap());
         assert_eq!(100, *results.get(1).unwrap());
         assert_eq!(300, *results.get(2).unwrap());
 
@@ -685,6 +1881,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/one".to_string(),
                     length: 5,
+                    ..Default::default()
                 },
             ),
             (
@@ -692,6 +1889,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/two".to_string(),
                     length: 2,
+                    ..Default::default()
                 },
             ),
             (
@@ -699,6 +1897,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/three".to_string(),
                     length: 4,
+                    ..Default::default()
                 },
             ),
             (
@@ -706,6 +1905,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/four".to_string(),
                     length: 3,
+                    ..Default::default()
                 },
             ),
             (
@@ -713,6 +1913,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/five".to_string(),
                     length: 1,
+                    ..Default::default()
                 },
             ),
             (
@@ -720,6 +1921,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/six".to_string(),
                     length: 6,
+                    ..Default::default()
                 },
             ),
         ]
@@ -759,21 +1961,21 @@ mod tests {
         let index = InvertedIndex::new(&keyspace)?;
 
         for (term, data) in terms.iter() {
-            index.terms().put(&term, data)?;
+            index.terms().put(term, data)?;
         }
         for (doc, data) in documents.iter() {
             index.docs().put(*doc, data)?;
         }
         for ((term, doc), data) in document_term_data.iter() {
-            index.postings().put(&term, *doc, data)?;
+            index.postings().put(term, *doc, data)?;
         }
 
         let scorer = SortingScorer {};
         let query: Vec<String> = ["a", "b"].into_iter().map(|s| s.to_string()).collect();
-        let results = index.search(&query, scorer, 10)?;
+        let results = ids(index.search(&query, scorer, 10)?);
 
         assert_eq!(6, results.len());
-        assert_eq!(600, *results.get(0).unwrap());
+        assert_eq!(600, *results.first().unwrap());
         assert_eq!(100, *results.get(1).unwrap());
         assert_eq!(300, *results.get(2).unwrap());
         assert_eq!(400, *results.get(3).unwrap());
@@ -800,6 +2002,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/one".to_string(),
                     length: 5,
+                    ..Default::default()
                 },
             ),
             (
@@ -807,6 +2010,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/two".to_string(),
                     length: 2,
+                    ..Default::default()
                 },
             ),
             (
@@ -814,6 +2018,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/three".to_string(),
                     length: 4,
+                    ..Default::default()
                 },
             ),
             (
@@ -821,6 +2026,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/four".to_string(),
                     length: 3,
+                    ..Default::default()
                 },
             ),
             (
@@ -828,6 +2034,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/five".to_string(),
                     length: 1,
+                    ..Default::default()
                 },
             ),
             (
@@ -835,6 +2042,7 @@ mod tests {
                 DocumentData {
                     path: "/hundred/six".to_string(),
                     length: 6,
+                    ..Default::default()
                 },
             ),
         ]
@@ -865,21 +2073,21 @@ mod tests {
         let index = InvertedIndex::new(&keyspace)?;
 
         for (term, data) in terms.iter() {
-            index.terms().put(&term, data)?;
+            index.terms().put(term, data)?;
         }
         for (doc, data) in documents.iter() {
             index.docs().put(*doc, data)?;
         }
         for ((term, doc), data) in document_term_data.iter() {
-            index.postings().put(&term, *doc, data)?;
+            index.postings().put(term, *doc, data)?;
         }
 
         let scorer = SortingScorer {};
         let query: Vec<String> = ["a", "b"].into_iter().map(|s| s.to_string()).collect();
-        let results = index.search(&query, scorer, 10)?;
+        let results = ids(index.search(&query, scorer, 10)?);
 
         assert_eq!(6, results.len());
-        assert_eq!(600, *results.get(0).unwrap());
+        assert_eq!(600, *results.first().unwrap());
         assert_eq!(100, *results.get(1).unwrap());
         assert_eq!(300, *results.get(2).unwrap());
         assert_eq!(400, *results.get(3).unwrap());
@@ -888,4 +2096,1030 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_add_document_records_positions() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_add_document_records_positions")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let id = index.add_document(
+            "pool/pool.rs",
+            &mut "connection pool\nPoolPool pool".as_bytes(),
+        )?;
+
+        let postings: Result<Vec<(DocumentId, DocumentTermData)>, Error> =
+            index.postings().get("pool").collect();
+        let postings = postings?;
+        assert_eq!(1, postings.len());
+
+        let (doc, data) = postings.first().unwrap();
+        assert_eq!(id, *doc);
+        assert_eq!(2, data.body_count);
+        assert_eq!(vec![1, 3], data.body_positions);
+        assert_eq!(2, data.path_count);
+        assert_eq!(vec![0, 1], data.path_positions);
+
+        // Partial tokens share the position of the word they came from.
+        let postings: Result<Vec<(DocumentId, DocumentTermData)>, Error> =
+            index.postings().get("Pool").collect();
+        let postings = postings?;
+        let (_, data) = postings.first().unwrap();
+        assert_eq!(2, data.body_count);
+        assert_eq!(vec![2], data.body_positions);
+
+        // Lengths count every token, so no term's count can be more than the length.
+        let doc_data = index.document(id)?.unwrap();
+        assert_eq!(6, doc_data.length);
+        assert_eq!(3, doc_data.path_length);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_phrase() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_phrase")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let in_order = index.add_document("a.txt", &mut "the connection pool".as_bytes())?;
+        let reversed = index.add_document("b.txt", &mut "the pool connection".as_bytes())?;
+        let apart = index.add_document("c.txt", &mut "connection to pool".as_bytes())?;
+        let in_path = index.add_document("connection/pool.rs", &mut "other".as_bytes())?;
+        let repeated =
+            index.add_document("d.txt", &mut "pool connection connection pool".as_bytes())?;
+
+        let query: Vec<String> = ["connection", "pool"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut results = ids(index.search_phrase(&query, SortingScorer {}, 10)?);
+        results.sort();
+
+        assert_eq!(vec![in_order, in_path, repeated], results);
+        assert!(!results.contains(&reversed));
+        assert!(!results.contains(&apart));
+
+        let query: Vec<String> = ["connection", "pool", "missing"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect();
+        let results = ids(index.search_phrase(&query, SortingScorer {}, 10)?);
+        assert!(results.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_window() {
+        let a: &[u64] = &[1, 10];
+        let b: &[u64] = &[4, 8];
+        let c: &[u64] = &[0, 9];
+
+        assert_eq!(Some(3), min_window(&[a, b, c], false));
+        assert_eq!(Some(9), min_window(&[a, b, c], true));
+        assert_eq!(Some(3), min_window(&[b, c, a], true));
+        assert_eq!(None, min_window(&[b, a, b], true));
+        assert_eq!(None, min_window(&[a, &[]], false));
+        assert_eq!(None, min_window(&[], false));
+    }
+
+    // A test scorer that gives every doc the same score.
+    struct ConstantScorer {}
+
+    impl Scorer for ConstantScorer {
+        fn score(
+            &self,
+            _doc_id: DocumentId,
+            _doc_data: &DocumentData,
+            _terms: &[String],
+            _term_data: &[TermData],
+            _doc_term_data: &[DocumentTermData],
+            _stats: &CollectionStats,
+        ) -> f32 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn test_search_near() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_near")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let adjacent = index.add_document("a.txt", &mut "alpha beta".as_bytes())?;
+        let reversed = index.add_document("b.txt", &mut "beta x alpha".as_bytes())?;
+        let far = index.add_document("c.txt", &mut "alpha x x x x beta".as_bytes())?;
+        index.add_document("d.txt", &mut "alpha".as_bytes())?;
+
+        let query: Vec<String> = ["alpha", "beta"]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        // Closer matches are ranked higher.
+        let results = ids(index.search_near(&query, 1, false, ConstantScorer {}, 10)?);
+        assert_eq!(vec![adjacent, reversed], results);
+
+        let results = ids(index.search_near(&query, 4, false, ConstantScorer {}, 10)?);
+        assert_eq!(vec![adjacent, reversed, far], results);
+
+        let results = ids(index.search_near(&query, 3, false, ConstantScorer {}, 10)?);
+        assert_eq!(vec![adjacent, reversed], results);
+
+        let results = ids(index.search_near(&query, 4, true, ConstantScorer {}, 10)?);
+        assert_eq!(vec![adjacent, far], results);
+
+        // With a distance of 0, the words have to be next to each other.
+        let results = ids(index.search_near(&query, 0, false, ConstantScorer {}, 10)?);
+        assert_eq!(vec![adjacent], results);
+
+        Ok(())
+    }
+
+    // A test scorer that counts how many of the terms are in the doc.
+    struct MatchCountingScorer {}
+
+    impl Scorer for MatchCountingScorer {
+        fn score(
+            &self,
+            _doc_id: DocumentId,
+            _doc_data: &DocumentData,
+            terms: &[String],
+            term_data: &[TermData],
+            doc_term_data: &[DocumentTermData],
+            _stats: &CollectionStats,
+        ) -> f32 {
+            assert_eq!(terms.len(), term_data.len());
+            assert_eq!(terms.len(), doc_term_data.len());
+            doc_term_data
+                .iter()
+                .filter(|data| data.body_count > 0)
+                .count() as f32
+        }
+    }
+
+    fn term(term: &str) -> Query {
+        Query::Term(term.to_string())
+    }
+
+    #[test]
+    fn test_search_query_boolean() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_query_boolean")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let pool_timeout = index.add_document("1", &mut "pool timeout".as_bytes())?;
+        let pool_test_timeout = index.add_document("2", &mut "pool test timeout".as_bytes())?;
+        let pool = index.add_document("3", &mut "pool".as_bytes())?;
+        let timeout = index.add_document("4", &mut "timeout".as_bytes())?;
+        index.add_document("5", &mut "test".as_bytes())?;
+
+        // +pool -test timeout
+        let query = Query::Boolean {
+            required: vec![term("pool")],
+            optional: vec![term("timeout")],
+            excluded: vec![term("test")],
+        };
+        let results = ids(index.search_query(&query, MatchCountingScorer {}, 10)?);
+        assert_eq!(vec![pool_timeout, pool], results);
+
+        // +pool +timeout
+        let query = Query::Boolean {
+            required: vec![term("pool"), term("timeout")],
+            optional: vec![],
+            excluded: vec![],
+        };
+        let mut results = ids(index.search_query(&query, MatchCountingScorer {}, 10)?);
+        results.sort();
+        assert_eq!(vec![pool_timeout, pool_test_timeout], results);
+
+        // pool timeout -test
+        let query = Query::Boolean {
+            required: vec![],
+            optional: vec![term("pool"), term("timeout")],
+            excluded: vec![term("test")],
+        };
+        let mut results = ids(index.search_query(&query, MatchCountingScorer {}, 10)?);
+        results.sort();
+        assert_eq!(vec![pool_timeout, pool, timeout], results);
+
+        // +pool +(test timeout) -"pool test"
+        let query = Query::Boolean {
+            required: vec![
+                term("pool"),
+                Query::Boolean {
+                    required: vec![],
+                    optional: vec![term("test"), term("timeout")],
+                    excluded: vec![],
+                },
+            ],
+            optional: vec![],
+            excluded: vec![Query::phrase(&["pool".to_string(), "test".to_string()])],
+        };
+        let results = ids(index.search_query(&query, MatchCountingScorer {}, 10)?);
+        assert_eq!(vec![pool_timeout], results);
+
+        // -test
+        let query = Query::Boolean {
+            required: vec![],
+            optional: vec![],
+            excluded: vec![term("test")],
+        };
+        let results = ids(index.search_query(&query, MatchCountingScorer {}, 10)?);
+        assert!(results.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_string() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_string")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let src_pool = index.add_document("src/pool.rs", &mut "connection pool".as_bytes())?;
+        let test_pool = index.add_document("test/pool.rs", &mut "pool timeout".as_bytes())?;
+        let src_title = index.add_document("src/title.rs", &mut "tittle src".as_bytes())?;
+        let request = index.add_document("src/http.rs", &mut "XMLHttpRequest".as_bytes())?;
+
+        let search = |query: &str| -> Result<Vec<DocumentId>, Error> {
+            let mut results = ids(index.search_string(query, ConstantScorer {}, 10)?);
+            results.sort();
+            Ok(results)
+        };
+
+        assert_eq!(vec![src_pool, src_title, request], search("path:src")?);
+        assert_eq!(vec![src_title], search("body:src")?);
+        assert_eq!(vec![src_pool, test_pool], search("+pool -title")?);
+        assert_eq!(vec![src_pool], search(r#""connection pool""#)?);
+        assert_eq!(vec![src_pool, test_pool], search("pool -path:title")?);
+        assert_eq!(vec![test_pool, src_title], search("tim* title~1")?);
+        assert_eq!(vec![request], search("HttpRequest")?);
+        assert_eq!(Vec::<DocumentId>::new(), search("missing*")?);
+        assert!(search("path:(src").is_err());
+        assert!(search("-pool").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_string_slop() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_string_slop")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let adjacent = index.add_document("a.txt", &mut "alpha beta gamma".as_bytes())?;
+        let one_gap = index.add_document("b.txt", &mut "alpha x beta gamma".as_bytes())?;
+        let two_gaps = index.add_document("c.txt", &mut "alpha x beta x gamma".as_bytes())?;
+        let reversed = index.add_document("d.txt", &mut "gamma beta alpha".as_bytes())?;
+
+        let search = |query: &str| -> Result<Vec<DocumentId>, Error> {
+            let mut results = ids(index.search_string(query, ConstantScorer {}, 10)?);
+            results.sort();
+            Ok(results)
+        };
+
+        // A slop of 0 is the same as the phrase on its own.
+        assert_eq!(vec![adjacent], search(r#""alpha beta"~0"#)?);
+        assert_eq!(vec![adjacent], search(r#""alpha beta gamma"~0"#)?);
+        assert_eq!(search(r#""beta alpha""#)?, search(r#""beta alpha"~0"#)?);
+        assert_eq!(vec![reversed], search(r#""beta alpha"~0"#)?);
+
+        // The slop is how many more words can be between the terms, which stay in order.
+        assert_eq!(vec![adjacent, one_gap], search(r#""alpha beta gamma"~1"#)?);
+        assert_eq!(
+            vec![adjacent, one_gap, two_gaps],
+            search(r#""alpha beta gamma"~2"#)?
+        );
+        assert_eq!(vec![reversed], search(r#""gamma beta alpha"~2"#)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_bm25() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_bm25")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let once =
+            index.add_document("a.txt", &mut "pool one two three four five six".as_bytes())?;
+        let twice = index.add_document("b.txt", &mut "pool pool one two".as_bytes())?;
+        let path = index.add_document("pool.txt", &mut "one two three".as_bytes())?;
+        index.add_document("c.txt", &mut "nothing to see".as_bytes())?;
+
+        let stats = index.collection_stats()?;
+        assert_eq!(4, stats.document_count);
+        assert_eq!(7 + 4 + 3 + 3, stats.total_length);
+
+        let terms = vec!["pool".to_string()];
+        assert_eq!(
+            vec![twice, path, once],
+            ids(index.search(&terms, Bm25Scorer::default(), 10)?)
+        );
+
+        let path_heavy = Bm25fScorer {
+            path: FieldWeight {
+                boost: 4.0,
+                b: 0.75,
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![path, twice, once],
+            ids(index.search(&terms, path_heavy, 10)?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_stats() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_collection_stats")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        assert_eq!(0, index.collection_stats()?.document_count);
+        assert_eq!(0.0, index.collection_stats()?.average_length());
+
+        let a = index.add_document("a/b.txt", &mut "one two three".as_bytes())?;
+        let b = index.add_document("c.txt", &mut "one".as_bytes())?;
+        assert_eq!(0, a);
+        assert_eq!(1, b);
+
+        let stats = index.collection_stats()?;
+        assert_eq!(2, stats.document_count);
+        assert_eq!(4, stats.total_length);
+        assert_eq!(5, stats.total_path_length);
+        assert_eq!(2.0, stats.average_length());
+        assert_eq!(2.5, stats.average_path_length());
+
+        // The stats should still be there after reopening the index.
+        drop(index);
+        let index = InvertedIndex::new(&keyspace)?;
+        assert_eq!(2, index.collection_stats()?.document_count);
+        let c = index.add_document("d.txt", &mut "".as_bytes())?;
+        assert_eq!(2, c);
+        assert_eq!(3, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_stats_count_tokens() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_collection_stats_count_tokens")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        index.add_document("fooBar.rs", &mut "fooBar baz\nPoolPool".as_bytes())?;
+        let b = index.add_document("b.txt", &mut "baz baz".as_bytes())?;
+
+        // The totals are the same as the counts of every term added together.
+        let total_count = |index: &InvertedIndex| -> Result<u64, Error> {
+            let mut total = 0;
+            for result in index.terms().prefix("") {
+                total += result?.1.count;
+            }
+            Ok(total)
+        };
+        let stats = index.collection_stats()?;
+        assert_eq!(9, stats.total_length);
+        assert_eq!(6, stats.total_path_length);
+        assert_eq!(
+            stats.total_length + stats.total_path_length,
+            total_count(&index)?
+        );
+
+        index.delete_document(b)?;
+        let stats = index.collection_stats()?;
+        assert_eq!(7, stats.total_length);
+        assert_eq!(4, stats.total_path_length);
+        assert_eq!(
+            stats.total_length + stats.total_path_length,
+            total_count(&index)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_term_data_across_documents() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_term_data_across_documents")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        index.add_document("a.txt", &mut "apple banana apple".as_bytes())?;
+        index.add_document("b.txt", &mut "banana cherry".as_bytes())?;
+        index.add_document("c.txt", &mut "apple apple apple".as_bytes())?;
+
+        let terms: Vec<String> = ["apple", "banana", "cherry", "txt", "missing"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let term_data = index.term_data(&terms)?;
+        let actual: Vec<(u64, u64)> = term_data
+            .iter()
+            .map(|data| (data.count, data.document_count))
+            .collect();
+        assert_eq!(vec![(5, 2), (2, 2), (1, 1), (3, 3), (0, 0)], actual);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_language_model() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_language_model")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let both = index.add_document("a.txt", &mut "red fish blue fish".as_bytes())?;
+        let red = index.add_document("b.txt", &mut "red car".as_bytes())?;
+        let blue = index.add_document("c.txt", &mut "blue sky blue sea".as_bytes())?;
+
+        // Docs missing one of the terms still get a finite score.
+        let terms: Vec<String> = ["red", "blue", "missing"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let results = ids(index.search(&terms, DirichletScorer::default(), 10)?);
+        assert_eq!(both, results[0]);
+        let mut rest = results[1..].to_vec();
+        rest.sort();
+        assert_eq!(vec![red, blue], rest);
+
+        let results = ids(index.search(&terms, JelinekMercerScorer::default(), 10)?);
+        assert_eq!(both, results[0]);
+        let mut rest = results[1..].to_vec();
+        rest.sort();
+        assert_eq!(vec![red, blue], rest);
+
+        // Pieces of words count toward the length, so a term in a single camelCase word
+        // still has a probability below 1.
+        index.add_document("d.txt", &mut "PoolPool".as_bytes())?;
+        let terms = vec!["Pool".to_string()];
+        let results = index.search(&terms, JelinekMercerScorer { lambda: 0.0 }, 10)?;
+        assert!(results[0].score < 0.0);
+        let results = index.search(&terms, DirichletScorer::default(), 10)?;
+        assert!(results[0].score < 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_document() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_delete_document")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let a = index.add_document("a.txt", &mut "apple banana apple".as_bytes())?;
+        let b = index.add_document("b.txt", &mut "banana cherry".as_bytes())?;
+
+        assert!(index.delete_document(a)?);
+        assert!(!index.delete_document(a)?);
+        assert!(index.docs().get(a)?.is_none());
+
+        let search = |words: &[&str]| -> Result<Vec<DocumentId>, Error> {
+            let terms: Vec<String> = words.iter().map(|s| s.to_string()).collect();
+            index.search(&terms, ConstantScorer {}, 10).map(ids)
+        };
+        assert_eq!(Vec::<DocumentId>::new(), search(&["apple"])?);
+        assert_eq!(vec![b], search(&["banana", "apple"])?);
+        assert_eq!(0, index.postings().get("apple").count());
+        assert_eq!(1, index.postings().get("banana").count());
+
+        // Terms only in the deleted doc are gone, and the rest are decremented.
+        assert!(index.terms().get("apple")?.is_none());
+        assert!(index.expand_prefix("app")?.is_empty());
+        let banana = index.terms().get("banana")?.unwrap();
+        assert_eq!(1, banana.count);
+        assert_eq!(1, banana.document_count);
+        let txt = index.terms().get("txt")?.unwrap();
+        assert_eq!(1, txt.count);
+
+        let stats = index.collection_stats()?;
+        assert_eq!(1, stats.document_count);
+        assert_eq!(2, stats.total_length);
+        assert_eq!(2, stats.total_path_length);
+
+        // Deleting every doc leaves an empty index.
+        assert!(index.delete_document(b)?);
+        assert_eq!(0, index.collection_stats()?.document_count);
+        assert!(index.expand_prefix("")?.is_empty());
+        assert_eq!(Vec::<DocumentId>::new(), search(&["banana"])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_document() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_upsert_document")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let search = |word: &str| -> Result<Vec<DocumentId>, Error> {
+            index
+                .search(&[word.to_string()], ConstantScorer {}, 10)
+                .map(ids)
+        };
+
+        assert_eq!(None, index.lookup_path("a.txt")?);
+        let old = index.upsert_document("a.txt", &mut "apple banana".as_bytes())?;
+        let other = index.upsert_document("b.txt", &mut "banana".as_bytes())?;
+        assert_eq!(Some(old), index.lookup_path("a.txt")?);
+        assert_eq!(Some(other), index.lookup_path("b.txt")?);
+
+        let new = index.upsert_document("a.txt", &mut "cherry banana".as_bytes())?;
+        assert_ne!(old, new);
+        assert_eq!(Some(new), index.lookup_path("a.txt")?);
+        assert!(index.docs().get(old)?.is_none());
+
+        assert_eq!(Vec::<DocumentId>::new(), search("apple")?);
+        assert_eq!(vec![new], search("cherry")?);
+        let mut bananas = search("banana")?;
+        bananas.sort();
+        assert_eq!(vec![other, new], bananas);
+
+        assert!(index.terms().get("apple")?.is_none());
+        let banana = index.terms().get("banana")?.unwrap();
+        assert_eq!(2, banana.count);
+        assert_eq!(2, banana.document_count);
+        assert_eq!(2, index.collection_stats()?.document_count);
+
+        // add_document points the path at the newest copy, and deleting it clears the path.
+        let copy = index.add_document("b.txt", &mut "banana".as_bytes())?;
+        assert_eq!(Some(copy), index.lookup_path("b.txt")?);
+        index.delete_document(other)?;
+        assert_eq!(Some(copy), index.lookup_path("b.txt")?);
+        index.delete_document(copy)?;
+        assert_eq!(None, index.lookup_path("b.txt")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_documents() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_add_documents")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let first = index.add_document("a.txt", &mut "apple".as_bytes())?;
+        let ids = index.add_documents([
+            ("b.txt", "apple banana".as_bytes()),
+            ("c.txt", "banana".as_bytes()),
+            ("b.txt", "cherry".as_bytes()),
+        ])?;
+        assert_eq!(3, ids.len());
+        assert!(!ids.contains(&first));
+        assert!(ids[0] < ids[1] && ids[1] < ids[2]);
+        assert_eq!(Some(ids[2]), index.lookup_path("b.txt")?);
+
+        let apple = index.terms().get("apple")?.unwrap();
+        assert_eq!(2, apple.document_count);
+        let txt = index.terms().get("txt")?.unwrap();
+        assert_eq!(4, txt.document_count);
+        assert_eq!(4, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_results() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_search_results")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let id = index.add_document(
+            "parse.rs",
+            &mut "let parse = 1;\nnaïveParse(parse)\n".as_bytes(),
+        )?;
+        index.add_document("other.rs", &mut "unrelated".as_bytes())?;
+
+        let results = index.search_string("parse Parse missing", Bm25Scorer::default(), 10)?;
+        assert_eq!(1, results.len());
+        let result = &results[0];
+        assert_eq!(id, result.id);
+        assert!(result.score > 0.0);
+        assert_eq!("parse.rs", result.document.path);
+        assert_eq!(7, result.document.length); // including the pieces of naïveParse
+        let matched = |term: &str, body_count, path_count| MatchedTerm {
+            term: term.to_string(),
+            body_count,
+            path_count,
+        };
+        assert_eq!(
+            vec![matched("parse", 2, 1), matched("Parse", 1, 0)],
+            result.matched_terms
+        );
+
+        // Partial tokens get their own column, counted in characters.
+        let location = |line, column, offset| Location {
+            line,
+            column,
+            offset,
+            length: 5,
+        };
+        let expected = vec![
+            Match {
+                term: "parse".to_string(),
+                location: location(0, 4, 4),
+            },
+            Match {
+                term: "Parse".to_string(),
+                location: location(1, 5, 21),
+            },
+            Match {
+                term: "parse".to_string(),
+                location: location(1, 11, 27),
+            },
+        ];
+        let terms = vec!["parse".to_string(), "Parse".to_string()];
+        assert_eq!(expected, index.matches(id, &terms)?);
+
+        // Terms that are only in the path have no locations.
+        assert!(index.matches(id, &["rs".to_string()])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_content() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_store_content")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        // Content isn't stored by default.
+        let index = InvertedIndex::new(&keyspace)?;
+        let id = index.add_document("a.txt", &mut "apple".as_bytes())?;
+        assert_eq!(None, index.content(id)?);
+
+        let options = IndexOptions {
+            store_content: true,
+            ..IndexOptions::default()
+        };
+        let index = InvertedIndex::with_options(&keyspace, options)?;
+        let old = index.upsert_document("a.txt", &mut "apple banana".as_bytes())?;
+        assert_eq!(Some("apple banana".to_string()), index.content(old)?);
+
+        // The setting is saved with the index.
+        let index = InvertedIndex::new(&keyspace)?;
+        let new = index.upsert_document("a.txt", &mut "cherry".as_bytes())?;
+        assert_eq!(Some("cherry".to_string()), index.content(new)?);
+        assert_eq!(None, index.content(old)?);
+
+        index.delete_document(new)?;
+        assert_eq!(None, index.content(new)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_analyzer() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_analyzer").open().unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let analyzer = Analyzer::default()
+            .with_filter(LowercaseFilter {})
+            .with_filter(StopwordFilter::english())
+            .with_filter(SynonymFilter::new().with_group(["car", "automobile"]));
+        let options = IndexOptions {
+            analyzer: Arc::new(analyzer),
+            ..IndexOptions::default()
+        };
+        let index = InvertedIndex::with_options(&keyspace, options)?;
+        let a = index.add_document("a.txt", &mut "The RED Car".as_bytes())?;
+        let b = index.add_document("b.txt", &mut "an automobile".as_bytes())?;
+        let c = index.add_document("c.txt", &mut "the cat in the hat".as_bytes())?;
+
+        let search = |query: &str| -> Result<Vec<DocumentId>, Error> {
+            let mut results = ids(index.search_string(query, ConstantScorer {}, 10)?);
+            results.sort();
+            Ok(results)
+        };
+
+        // The same filters are used for the documents and the query.
+        let mut both = vec![a, b];
+        both.sort();
+        assert_eq!(vec![a], search("red")?);
+        assert_eq!(vec![a], search("RED")?);
+        assert_eq!(both, search("CAR")?);
+        assert_eq!(both, search("automobile")?);
+        assert_eq!(vec![a], search("\"red car\"")?);
+        assert!(index.terms().get("the")?.is_none());
+
+        // Stopwords are left out of phrases, but still keep the other terms apart.
+        assert_eq!(vec![c], search("\"cat in the hat\"")?);
+        assert_eq!(vec![c], search("cat.in.the.hat")?);
+        assert!(search("\"cat hat\"")?.is_empty());
+        assert!(search("\"cat the hat\"")?.is_empty());
+        assert_eq!(vec![c], search("\"cat the hat\"~1")?);
+        assert!(search("\"cat hat\"~1")?.is_empty());
+        assert_eq!(vec![c], search("\"cat hat\"~2")?);
+        assert!(index.terms().get("Car")?.is_none());
+
+        // Synonyms don't count as more words.
+        assert_eq!(2, index.document(a)?.unwrap().length);
+
+        // The analyzer is saved with the index, and it can only be changed once it's empty.
+        assert!(matches!(
+            InvertedIndex::new(&keyspace),
+            Err(Error::AnalyzerMismatch {})
+        ));
+        for id in [a, b, c] {
+            index.delete_document(id)?;
+        }
+        let index = InvertedIndex::new(&keyspace)?;
+        index.add_document("d.txt", &mut "The Car".as_bytes())?;
+        assert!(index.terms().get("The")?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stemming() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_stemming").open().unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let analyzer = Analyzer::default()
+            .with_filter(NormalizeFilter::default())
+            .with_filter(StemFilter::new(Language::English).with_exact());
+        let options = IndexOptions {
+            analyzer: Arc::new(analyzer),
+            ..IndexOptions::default()
+        };
+        let index = InvertedIndex::with_options(&keyspace, options)?;
+        let a = index.add_document("a.txt", &mut "Indexing files".as_bytes())?;
+        let b = index.add_document("b.txt", &mut "it was indexed".as_bytes())?;
+
+        let search = |query: &str| -> Result<Vec<DocumentId>, Error> {
+            let mut results = ids(index.search_string(query, ConstantScorer {}, 10)?);
+            results.sort();
+            Ok(results)
+        };
+
+        let mut both = vec![a, b];
+        both.sort();
+        assert_eq!(both, search("indexes")?);
+        assert_eq!(vec![a], search("=indexing")?);
+        assert_eq!(vec![b], search("=Indexed")?);
+        assert_eq!(vec![a], search("\"indexed file\"")?);
+
+        // The exact forms don't count as more words.
+        assert_eq!(2, index.document(a)?.unwrap().length);
+        assert_eq!(4, index.collection_stats()?.total_path_length);
+
+        // Matches point at the words as they were written.
+        let matches = index.matches(a, &["index".to_string()])?;
+        assert_eq!(1, matches.len());
+        assert_eq!(0, matches[0].location.offset);
+        assert_eq!(8, matches[0].location.length);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_write_leaves_index_unchanged() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_failed_write_leaves_index_unchanged")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let old = index.add_document("a.txt", &mut "apple banana".as_bytes())?;
+
+        let search = |word: &str| -> Result<Vec<DocumentId>, Error> {
+            index
+                .search(&[word.to_string()], ConstantScorer {}, 10)
+                .map(ids)
+        };
+
+        // Stage the whole replacement, and then fail partway through committing it.
+        let mut processor = index.process_document("a.txt", &mut "banana cherry".as_bytes())?;
+        processor.replace = true;
+        let mut batch = index.batch();
+        index.allocate_id(&mut batch, &mut processor)?;
+        index.write_documents(&mut batch, vec![processor])?;
+        batch.fail_after(2);
+        assert!(batch.commit().is_err());
+
+        assert_eq!(Some(old), index.lookup_path("a.txt")?);
+        assert_eq!(vec![old], search("apple")?);
+        assert_eq!(vec![old], search("banana")?);
+        assert_eq!(Vec::<DocumentId>::new(), search("cherry")?);
+        assert!(index.terms().get("cherry")?.is_none());
+        assert_eq!(1, index.terms().get("banana")?.unwrap().document_count);
+        assert_eq!(1, index.collection_stats()?.document_count);
+        assert_eq!(1, index.docs().iter().count());
+
+        // The same write goes through without the failure.
+        let new = index.upsert_document("a.txt", &mut "banana cherry".as_bytes())?;
+        assert_eq!(Some(new), index.lookup_path("a.txt")?);
+        assert_eq!(Vec::<DocumentId>::new(), search("apple")?);
+        assert_eq!(vec![new], search("cherry")?);
+        assert_eq!(1, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_same_path_in_one_batch() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_replace_same_path_in_one_batch")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let old = index.add_document("a.txt", &mut "apple".as_bytes())?;
+
+        let mut batch = index.batch();
+        let mut processors = Vec::new();
+        let mut ids = Vec::new();
+        for (path, text) in [
+            ("a.txt", "banana"),
+            ("b.txt", "banana"),
+            ("a.txt", "cherry"),
+        ] {
+            let mut processor = index.process_document(path, &mut text.as_bytes())?;
+            processor.replace = true;
+            ids.push(index.allocate_id(&mut batch, &mut processor)?);
+            processors.push(processor);
+        }
+        index.write_documents(&mut batch, processors)?;
+        batch.commit()?;
+
+        // Only the last a.txt is left, and nothing points at the others.
+        assert!(index.document(old)?.is_none());
+        assert!(index.document(ids[0])?.is_none());
+        assert_eq!(Some(ids[2]), index.lookup_path("a.txt")?);
+        assert_eq!(2, index.docs().iter().count());
+        assert_eq!(2, index.collection_stats()?.document_count);
+        assert!(index.terms().get("apple")?.is_none());
+        assert_eq!(1, index.terms().get("banana")?.unwrap().document_count);
+        assert_eq!(1, index.terms().get("cherry")?.unwrap().document_count);
+        assert_eq!(1, index.postings().get("banana").count());
+
+        // Removing a document twice in one batch only counts it once.
+        let mut batch = index.batch();
+        assert!(index.remove_document(&mut batch, ids[1])?);
+        assert!(!index.remove_document(&mut batch, ids[1])?);
+        batch.commit()?;
+        assert_eq!(1, index.collection_stats()?.document_count);
+        assert!(index.terms().get("banana")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deleted_ids_are_not_reused() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_deleted_ids_are_not_reused")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)
+            .unwrap()
+            .delete(&keyspace)
+            .unwrap();
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let a = index.add_document("a.txt", &mut "apple".as_bytes())?;
+        let b = index.add_document("b.txt", &mut "banana".as_bytes())?;
+        index.delete_document(b)?;
+        let c = index.add_document("c.txt", &mut "cherry".as_bytes())?;
+        assert_eq!(vec![0, 1, 2], vec![a, b, c]);
+
+        drop(index);
+        let index = InvertedIndex::with_id_allocator(&keyspace, RandomIds {})?;
+        let d = index.add_document("d.txt", &mut "date".as_bytes())?;
+        assert!(![a, b, c].contains(&d));
+        assert_eq!(
+            vec![d],
+            ids(index.search(&["date".to_string()], ConstantScorer {}, 10)?)
+        );
+
+        // Re-adding an unchanged file can't reuse the hash of its contents as the id.
+        drop(index);
+        let index = InvertedIndex::with_id_allocator(&keyspace, ContentHashIds {})?;
+        let e = index.upsert_document("e.txt", &mut "elderberry".as_bytes())?;
+        let again = index.upsert_document("e.txt", &mut "elderberry".as_bytes())?;
+        assert_ne!(e, again);
+        assert_eq!(Some(again), index.lookup_path("e.txt")?);
+        assert!(index.document(e)?.is_none());
+        assert_eq!(
+            vec![again],
+            ids(index.search(&["elderberry".to_string()], ConstantScorer {}, 10)?)
+        );
+
+        Ok(())
+    }
 }
@@ -1,4 +1,13 @@
+pub mod analyzer;
+pub mod crawl;
 pub mod error;
+pub mod grep;
+pub mod id;
 pub mod index;
 pub mod parse;
+pub mod query;
+pub mod scorer;
+pub mod snippet;
 pub mod store;
+pub mod watch;
+pub mod writer;
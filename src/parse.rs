@@ -1,7 +1,9 @@
 // Metadata about one occurrence of a token in a document.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Occurrence {
     pub position: usize, // N means this is the nth word in the doc
     pub offset: usize,   // the byte offset of the start of the word in the doc
+    pub length: usize,   // the length of the word in the doc, in bytes
     pub line: usize,     // which line the word is on
     pub column: usize,   // the character offset of the start of the word in the line
     pub partial: bool,   // whether this is just part of a word or the whole word
@@ -99,6 +101,7 @@ fn split_token(token: &TokenSlice, indexer: &mut impl TokenProcessor) {
                     line: token.occurrence.line,
                     column: token.occurrence.column,
                     offset: token.occurrence.offset + word_start,
+                    length: word_end - last_char_len - word_start,
                     partial: true,
                 },
             });
@@ -117,6 +120,7 @@ fn split_token(token: &TokenSlice, indexer: &mut impl TokenProcessor) {
                 line: token.occurrence.line,
                 column: token.occurrence.column,
                 offset: token.occurrence.offset + word_start,
+                length: word_end - word_start,
                 partial: true,
             },
         });
@@ -172,10 +176,11 @@ pub fn parse_text(text: &str, indexer: &mut impl TokenProcessor) {
         let token = TokenSlice {
             token: &text[start..end],
             occurrence: Occurrence {
-                position: position,
+                position,
                 line,
                 column: word_column,
                 offset: start,
+                length: end - start,
                 partial: false,
             },
         };
@@ -195,14 +200,15 @@ mod tests {
 
     impl<'a> TokenSlice<'a> {
         fn to_token(&self) -> Token {
-            return Token {
+            Token {
                 token: self.token.to_string(),
                 position: self.occurrence.position,
                 line: self.occurrence.line,
                 column: self.occurrence.column,
                 offset: self.occurrence.offset,
+                length: self.occurrence.length,
                 partial: self.occurrence.partial,
-            };
+            }
         }
     }
 
@@ -212,6 +218,7 @@ mod tests {
         line: usize,
         column: usize,
         offset: usize,
+        length: usize,
         partial: bool,
     }
 
@@ -221,7 +228,7 @@ mod tests {
 
     impl TestIndex {
         fn new() -> Self {
-            return TestIndex { tokens: Vec::new() };
+            TestIndex { tokens: Vec::new() }
         }
     }
 
@@ -239,13 +246,13 @@ mod tests {
 
         assert_eq!(1, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -256,13 +263,13 @@ mod tests {
 
         assert_eq!(2, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("bar", token.token);
@@ -270,7 +277,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(4, token.column);
         assert_eq!(4, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -281,13 +288,13 @@ mod tests {
 
         assert_eq!(2, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(2, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!(1, token.position);
@@ -295,7 +302,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(6, token.column);
         assert_eq!(6, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -306,13 +313,13 @@ mod tests {
 
         assert_eq!(2, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("bar", token.token);
@@ -320,7 +327,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(4, token.column);
         assert_eq!(4, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -331,13 +338,14 @@ mod tests {
 
         assert_eq!(4, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo123bar", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert_eq!(9, token.length);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("foo", token.token);
@@ -345,7 +353,8 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(true, token.partial);
+        assert_eq!(3, token.length);
+        assert!(token.partial);
 
         let token = index.tokens.get(2).unwrap();
         assert_eq!("123", token.token);
@@ -353,7 +362,8 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(3, token.offset);
-        assert_eq!(true, token.partial);
+        assert_eq!(3, token.length);
+        assert!(token.partial);
 
         let token = index.tokens.get(3).unwrap();
         assert_eq!("bar", token.token);
@@ -361,7 +371,8 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(6, token.offset);
-        assert_eq!(true, token.partial);
+        assert_eq!(3, token.length);
+        assert!(token.partial);
     }
 
     #[test]
@@ -372,13 +383,13 @@ mod tests {
 
         assert_eq!(2, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("bar", token.token);
@@ -386,7 +397,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(4, token.column);
         assert_eq!(4, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -397,13 +408,13 @@ mod tests {
 
         assert_eq!(4, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("FooBar123", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("Foo", token.token);
@@ -411,7 +422,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(2).unwrap();
         assert_eq!("Bar", token.token);
@@ -419,7 +430,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(3, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(3).unwrap();
         assert_eq!("123", token.token);
@@ -427,7 +438,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(6, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
     }
 
     #[test]
@@ -438,13 +449,13 @@ mod tests {
 
         assert_eq!(7, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("福", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("福foo福bar福", token.token);
@@ -452,7 +463,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(4, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(2).unwrap();
         assert_eq!("福", token.token);
@@ -460,7 +471,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(4, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(3).unwrap();
         assert_eq!("foo", token.token);
@@ -468,7 +479,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(7, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(4).unwrap();
         assert_eq!("福", token.token);
@@ -476,7 +487,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(10, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(5).unwrap();
         assert_eq!("bar", token.token);
@@ -484,7 +495,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(13, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(6).unwrap();
         assert_eq!("福", token.token);
@@ -492,7 +503,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(2, token.column);
         assert_eq!(16, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
     }
 
     #[test]
@@ -503,13 +514,13 @@ mod tests {
 
         assert_eq!(2, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(3, token.column);
         assert_eq!(9, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("bar", token.token);
@@ -517,7 +528,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(7, token.column);
         assert_eq!(16, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -528,13 +539,13 @@ mod tests {
 
         assert_eq!(2, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("foo", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("bar", token.token);
@@ -542,7 +553,7 @@ mod tests {
         assert_eq!(2, token.line);
         assert_eq!(2, token.column);
         assert_eq!(9, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
     }
 
     #[test]
@@ -553,13 +564,13 @@ mod tests {
 
         assert_eq!(4, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("XMLHttpRequest", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("XML", token.token);
@@ -567,7 +578,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(2).unwrap();
         assert_eq!("Http", token.token);
@@ -575,7 +586,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(3, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(3).unwrap();
         assert_eq!("Request", token.token);
@@ -583,7 +594,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(7, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
     }
 
     #[test]
@@ -594,13 +605,13 @@ mod tests {
 
         assert_eq!(4, index.tokens.len());
 
-        let token = index.tokens.get(0).unwrap();
+        let token = index.tokens.first().unwrap();
         assert_eq!("ÜÜÜÜttpÜequest", token.token);
         assert_eq!(0, token.position);
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(false, token.partial);
+        assert!(!token.partial);
 
         let token = index.tokens.get(1).unwrap();
         assert_eq!("ÜÜÜ", token.token);
@@ -608,7 +619,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(0, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(2).unwrap();
         assert_eq!("Üttp", token.token);
@@ -616,7 +627,7 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(6, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
 
         let token = index.tokens.get(3).unwrap();
         assert_eq!("Üequest", token.token);
@@ -624,6 +635,6 @@ mod tests {
         assert_eq!(0, token.line);
         assert_eq!(0, token.column);
         assert_eq!(11, token.offset);
-        assert_eq!(true, token.partial);
+        assert!(token.partial);
     }
 }
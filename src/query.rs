@@ -0,0 +1,886 @@
+use std::ops::Range;
+
+use crate::{
+    analyzer::{Analyzer, EXACT_MARKER},
+    error::Error,
+    index::InvertedIndex,
+    parse::{TokenProcessor, TokenSlice},
+};
+
+// Which part of a document a query applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Body,
+    Path,
+}
+
+// A query tree that can be run against an inverted index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    // Docs that have the term.
+    Term(String),
+
+    // Docs that have all of the terms in order, with each one offsets[i] positions after
+    // the first. The terms are next to each other unless the analyzer dropped words
+    // between them, like stopwords, which still take up positions in documents.
+    Phrase {
+        terms: Vec<String>,
+        offsets: Vec<u64>,
+    },
+
+    // Docs that have all of the terms with at most distance other positions between them,
+    // so a distance of 0 means they're next to each other. If ordered is true, the terms must also appear in the same order.
+    Near {
+        terms: Vec<String>,
+        distance: u64,
+        ordered: bool,
+    },
+
+    // Docs that match all of the required clauses, or at least one of the optional
+    // clauses if there are no required ones, and none of the excluded clauses.
+    // Optional clauses that match still count towards the score.
+    Boolean {
+        required: Vec<Query>,
+        optional: Vec<Query>,
+        excluded: Vec<Query>,
+    },
+
+    // Docs that match the query using only the given field.
+    InField {
+        field: Field,
+        query: Box<Query>,
+    },
+}
+
+impl Query {
+    // Docs that have any of the terms.
+    pub fn any(terms: &[String]) -> Query {
+        Query::Boolean {
+            required: Vec::new(),
+            optional: terms.iter().map(|term| Query::Term(term.clone())).collect(),
+            excluded: Vec::new(),
+        }
+    }
+
+    // Docs that have all of the terms next to each other, in order.
+    pub fn phrase(terms: &[String]) -> Query {
+        Query::Phrase {
+            terms: terms.to_vec(),
+            offsets: (0..terms.len() as u64).collect(),
+        }
+    }
+
+    // Docs that have all of the terms.
+    pub fn all(terms: &[String]) -> Query {
+        Query::Boolean {
+            required: terms.iter().map(|term| Query::Term(term.clone())).collect(),
+            optional: Vec::new(),
+            excluded: Vec::new(),
+        }
+    }
+
+    // The terms that can contribute to a doc's score, in the order they first appear.
+    // Terms that only appear in excluded clauses are left out.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.collect_terms(&mut terms);
+        terms
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<String>) {
+        let mut add = |term: &String| {
+            if !terms.contains(term) {
+                terms.push(term.clone());
+            }
+        };
+        match self {
+            Query::Term(term) => add(term),
+            Query::Phrase { terms: phrase, .. } => phrase.iter().for_each(add),
+            Query::Near { terms: near, .. } => near.iter().for_each(add),
+            Query::Boolean {
+                required, optional, ..
+            } => {
+                for clause in required.iter().chain(optional.iter()) {
+                    clause.collect_terms(terms);
+                }
+            }
+            Query::InField { query, .. } => query.collect_terms(terms),
+        }
+    }
+}
+
+// How a clause of a query string affects which docs match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Occur {
+    Should,  // no prefix
+    Must,    // a "+" prefix
+    MustNot, // a "-" prefix
+}
+
+// One term of a bare word in a query string, along with the camelCase and
+// alphanumeric pieces it was split into, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordTerm {
+    pub term: String,
+    pub alternatives: Vec<String>, // other terms the analyzer added for the word, like synonyms
+    pub parts: Vec<String>,
+    pub position: usize, // where the word is in the text that was analyzed
+}
+
+// A parsed query string, before any lookups in the index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryNode {
+    // A bare word, split into terms the same way as document text.
+    Word(Vec<WordTerm>),
+
+    // A quoted phrase, optionally followed by ~N to allow up to N more words between its
+    // terms, which still have to be in order, so "a b"~1 matches "a x b". Offsets are where
+    // each term is relative to the first, as in Query::Phrase.
+    Phrase {
+        terms: Vec<String>,
+        offsets: Vec<u64>,
+        slop: Option<u64>,
+    },
+
+    // A word followed by "*".
+    Prefix(String),
+
+    // A word followed by "~N", matching terms within N edits.
+    Fuzzy {
+        term: String,
+        distance: u32,
+    },
+
+    // A list of clauses, either the whole query or in parentheses.
+    Group(Vec<Clause>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub occur: Occur,
+    pub field: Option<Field>,
+    pub node: QueryNode,
+    pub span: Range<usize>, // the byte range of the clause in the query string
+}
+
+// The edit distance used for a fuzzy word with no number after the "~".
+const DEFAULT_FUZZY_DISTANCE: u32 = 2;
+
+// Parses a query string, such as `path:src "exact phrase" foo* -bar title~2`.
+//
+// Clauses are separated by whitespace, and can be prefixed with "+" to require them,
+// "-" to exclude them, and "path:" or "body:" to only match that part of the doc. Excluding
+// only works along with other clauses, so "-foo" on its own is an error.
+// Parentheses group clauses together. A word starting with "=" only matches the word as
+// written, if the analyzer stems words and keeps their exact forms.
+pub fn parse_query(text: &str) -> Result<QueryNode, Error> {
+    parse_query_with(text, &Analyzer::default())
+}
+
+// Parses a query string, turning its words into terms with the analyzer.
+pub fn parse_query_with(text: &str, analyzer: &Analyzer) -> Result<QueryNode, Error> {
+    let mut parser = QueryParser {
+        text,
+        pos: 0,
+        analyzer,
+    };
+    Ok(QueryNode::Group(parser.parse_clauses(None)?))
+}
+
+fn syntax_error(message: &str, span: Range<usize>) -> Error {
+    Error::QuerySyntaxError {
+        message: message.to_string(),
+        span,
+    }
+}
+
+// Excluded clauses only remove docs that something else matched, so a group with nothing
+// but excluded clauses would never match anything.
+fn check_included(clauses: &[Clause]) -> Result<(), Error> {
+    if let (Some(first), Some(last)) = (clauses.first(), clauses.last())
+        && clauses.iter().all(|clause| clause.occur == Occur::MustNot)
+    {
+        return Err(syntax_error(
+            "there's nothing to exclude from",
+            first.span.start..last.span.end,
+        ));
+    }
+    Ok(())
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '"' | '(' | ')' | '*' | '~')
+}
+
+struct QueryParser<'a> {
+    text: &'a str,
+    pos: usize,
+    analyzer: &'a Analyzer,
+}
+
+impl<'a> QueryParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    // The span of the next character, or the end of the text.
+    fn next_span(&self) -> Range<usize> {
+        let len = self.peek().map_or(0, |c| c.len_utf8());
+        self.pos..self.pos + len
+    }
+
+    fn read_word(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(is_word_char) {
+            self.bump();
+        }
+        &self.text[start..self.pos]
+    }
+
+    fn read_number(&mut self) -> Result<Option<u64>, Error> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        match self.text[start..self.pos].parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(syntax_error("number is too large", start..self.pos)),
+        }
+    }
+
+    // Parses clauses until the end of the text, or the ")" closing the group
+    // that was opened at group_start.
+    fn parse_clauses(&mut self, group_start: Option<usize>) -> Result<Vec<Clause>, Error> {
+        let mut clauses = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => {
+                    if let Some(start) = group_start {
+                        return Err(syntax_error("unclosed '('", start..start + 1));
+                    }
+                    check_included(&clauses)?;
+                    return Ok(clauses);
+                }
+                Some(')') => {
+                    if group_start.is_none() {
+                        return Err(syntax_error("unmatched ')'", self.next_span()));
+                    }
+                    self.bump();
+                    check_included(&clauses)?;
+                    return Ok(clauses);
+                }
+                Some(_) => {
+                    if let Some(clause) = self.parse_clause()? {
+                        clauses.push(clause);
+                    }
+                }
+            }
+        }
+    }
+
+    // Parses one clause, or returns None if it has no terms in it, such as "!!!".
+    fn parse_clause(&mut self) -> Result<Option<Clause>, Error> {
+        let start = self.pos;
+        let occur = match self.peek() {
+            Some('+') => Occur::Must,
+            Some('-') => Occur::MustNot,
+            _ => Occur::Should,
+        };
+        if occur != Occur::Should {
+            self.bump();
+        }
+
+        let field = self.parse_field();
+
+        let Some(node) = self.parse_node()? else {
+            return Ok(None);
+        };
+        Ok(Some(Clause {
+            occur,
+            field,
+            node,
+            span: start..self.pos,
+        }))
+    }
+
+    // Parses a "body:" or "path:" prefix, if there is one. Any other colon is part of
+    // the word, so things like "std::io" and "http://x" can be searched for.
+    fn parse_field(&mut self) -> Option<Field> {
+        let rest = &self.text[self.pos..];
+        let (prefix, field) = if rest.starts_with("body:") {
+            ("body:", Field::Body)
+        } else if rest.starts_with("path:") {
+            ("path:", Field::Path)
+        } else {
+            return None;
+        };
+        self.pos += prefix.len();
+        Some(field)
+    }
+
+    fn parse_node(&mut self) -> Result<Option<QueryNode>, Error> {
+        let start = self.pos;
+        match self.peek() {
+            Some('"') => {
+                self.bump();
+                let Some(len) = self.text[self.pos..].find('"') else {
+                    return Err(syntax_error("unclosed '\"'", start..self.text.len()));
+                };
+                let word_terms = self.analyze(&self.text[self.pos..self.pos + len]);
+                let (terms, offsets) = phrase_terms(&word_terms);
+                self.pos += len + 1;
+                if terms.is_empty() {
+                    return Err(syntax_error("phrase has no terms", start..self.pos));
+                }
+                let slop = if self.peek() == Some('~') {
+                    let tilde = self.next_span();
+                    self.bump();
+                    let Some(slop) = self.read_number()? else {
+                        return Err(syntax_error("expected a number after '~'", tilde));
+                    };
+                    Some(slop)
+                } else {
+                    None
+                };
+                Ok(Some(QueryNode::Phrase {
+                    terms,
+                    offsets,
+                    slop,
+                }))
+            }
+            Some('(') => {
+                self.bump();
+                Ok(Some(QueryNode::Group(self.parse_clauses(Some(start))?)))
+            }
+            Some(c) if is_word_char(c) => {
+                let word = self.read_word().to_string();
+                match self.peek() {
+                    Some('*') => {
+                        self.bump();
+                        Ok(Some(QueryNode::Prefix(self.normalize(word))))
+                    }
+                    Some('~') => {
+                        self.bump();
+                        let distance = match self.read_number()? {
+                            Some(n) => u32::try_from(n).map_err(|_| {
+                                syntax_error(
+                                    "number is too large",
+                                    start + word.len() + 1..self.pos,
+                                )
+                            })?,
+                            None => DEFAULT_FUZZY_DISTANCE,
+                        };
+                        Ok(Some(QueryNode::Fuzzy {
+                            term: self.normalize(word),
+                            distance,
+                        }))
+                    }
+                    _ => {
+                        let terms = match word.strip_prefix(EXACT_MARKER) {
+                            Some(rest) => self.analyze(rest).into_iter().map(exact).collect(),
+                            None => self.analyze(&word),
+                        };
+                        if terms.is_empty() {
+                            return Ok(None);
+                        }
+                        Ok(Some(QueryNode::Word(terms)))
+                    }
+                }
+            }
+            _ => Err(syntax_error(
+                "expected a word, phrase, or group",
+                self.next_span(),
+            )),
+        }
+    }
+}
+
+// Collects the terms of a word, with the partial tokens attached to the whole word.
+struct WordTermCollector {
+    terms: Vec<WordTerm>,
+    part_offset: usize, // the offset of the last partial token
+}
+
+impl TokenProcessor for WordTermCollector {
+    fn process_token(&mut self, token: &TokenSlice) {
+        let occurrence = &token.occurrence;
+        let term = token.token.to_string();
+        match self.terms.last_mut() {
+            // Filters can add more than one token for the same piece of text. Only the first
+            // is used for a piece of a word, since all of the pieces are required.
+            Some(last) if occurrence.partial && occurrence.position == last.position => {
+                if last.parts.is_empty() || occurrence.offset != self.part_offset {
+                    last.parts.push(term);
+                    self.part_offset = occurrence.offset;
+                }
+            }
+            Some(last) if occurrence.position == last.position => {
+                if last.term != term && !last.alternatives.contains(&term) {
+                    last.alternatives.push(term);
+                }
+            }
+            // The pieces of a word that a filter removed are left out along with it.
+            _ if occurrence.partial => {}
+            _ => {
+                self.terms.push(WordTerm {
+                    term,
+                    alternatives: Vec::new(),
+                    parts: Vec::new(),
+                    position: occurrence.position,
+                });
+            }
+        }
+    }
+}
+
+impl QueryParser<'_> {
+    // Splits text into terms with the same analyzer used for documents.
+    fn analyze(&self, text: &str) -> Vec<WordTerm> {
+        let mut collector = WordTermCollector {
+            terms: Vec::new(),
+            part_offset: 0,
+        };
+        self.analyzer.analyze(text, &mut collector);
+        collector.terms
+    }
+
+    // Turns a prefix or fuzzy word into the term the analyzer would make of it, or leaves it
+    // alone if the analyzer removes it.
+    fn normalize(&self, word: String) -> String {
+        match self.analyze(&word).into_iter().next() {
+            Some(word_term) => word_term.term,
+            None => word,
+        }
+    }
+}
+
+// Uses the exact form of a word that the analyzer added, if there is one, in place of its stem.
+fn exact(word_term: WordTerm) -> WordTerm {
+    match word_term
+        .alternatives
+        .iter()
+        .find(|term| term.starts_with(EXACT_MARKER))
+    {
+        Some(term) => WordTerm {
+            term: term.clone(),
+            alternatives: Vec::new(),
+            parts: Vec::new(),
+            position: word_term.position,
+        },
+        None => word_term,
+    }
+}
+
+// The terms of a phrase, and where each one is relative to the first.
+fn phrase_terms(word_terms: &[WordTerm]) -> (Vec<String>, Vec<u64>) {
+    let first = word_terms.first().map_or(0, |word_term| word_term.position);
+    word_terms
+        .iter()
+        .map(|word_term| {
+            let offset = (word_term.position - first) as u64;
+            (word_term.term.clone(), offset)
+        })
+        .unzip()
+}
+
+impl QueryNode {
+    // Turns the parsed query into a query tree that can be searched, looking up
+    // the terms in the index that match any prefix or fuzzy words.
+    pub fn to_query(&self, index: &InvertedIndex) -> Result<Query, Error> {
+        Ok(match self {
+            QueryNode::Word(terms) => {
+                if terms.len() > 1 {
+                    // Words with punctuation in them, like "foo.bar", are a phrase.
+                    let (terms, offsets) = phrase_terms(terms);
+                    Query::Phrase { terms, offsets }
+                } else {
+                    let term = &terms[0];
+                    if term.parts.is_empty() && term.alternatives.is_empty() {
+                        Query::Term(term.term.clone())
+                    } else {
+                        // Match the whole word, any of its alternatives, or all of its pieces,
+                        // so that "HttpRequest" can find "XMLHttpRequest".
+                        let mut optional = vec![Query::Term(term.term.clone())];
+                        optional.extend(term.alternatives.iter().cloned().map(Query::Term));
+                        if !term.parts.is_empty() {
+                            optional.push(Query::all(&term.parts));
+                        }
+                        Query::Boolean {
+                            required: Vec::new(),
+                            optional,
+                            excluded: Vec::new(),
+                        }
+                    }
+                }
+            }
+            QueryNode::Phrase {
+                terms,
+                offsets,
+                slop: None | Some(0),
+            } => Query::Phrase {
+                terms: terms.clone(),
+                offsets: offsets.clone(),
+            },
+            QueryNode::Phrase {
+                terms,
+                offsets,
+                slop: Some(slop),
+            } => {
+                // Words dropped from the phrase, like stopwords, count towards the distance.
+                let span = offsets.last().map_or(0, |last| last + 1);
+                Query::Near {
+                    terms: terms.clone(),
+                    distance: span - terms.len() as u64 + slop,
+                    ordered: true,
+                }
+            }
+            QueryNode::Prefix(prefix) => Query::any(&index.expand_prefix(prefix)?),
+            QueryNode::Fuzzy { term, distance } => {
+                Query::any(&index.expand_fuzzy(term, *distance)?)
+            }
+            QueryNode::Group(clauses) => {
+                let mut required = Vec::new();
+                let mut optional = Vec::new();
+                let mut excluded = Vec::new();
+                for clause in clauses.iter() {
+                    let mut query = clause.node.to_query(index)?;
+                    if let Some(field) = clause.field {
+                        query = Query::InField {
+                            field,
+                            query: Box::new(query),
+                        };
+                    }
+                    match clause.occur {
+                        Occur::Should => optional.push(query),
+                        Occur::Must => required.push(query),
+                        Occur::MustNot => excluded.push(query),
+                    }
+                }
+                Query::Boolean {
+                    required,
+                    optional,
+                    excluded,
+                }
+            }
+        })
+    }
+}
+
+// The number of single character insertions, deletions, or substitutions to turn a into b.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{Language, LowercaseFilter, StemFilter, StopwordFilter, SynonymFilter};
+
+    #[test]
+    fn test_query_terms() {
+        let query = Query::Boolean {
+            required: vec![
+                Query::Term("a".to_string()),
+                Query::phrase(&["b".to_string(), "a".to_string()]),
+            ],
+            optional: vec![Query::Near {
+                terms: vec!["c".to_string(), "d".to_string()],
+                distance: 3,
+                ordered: false,
+            }],
+            excluded: vec![Query::Term("e".to_string())],
+        };
+
+        assert_eq!(vec!["a", "b", "c", "d"], query.terms());
+    }
+
+    fn word(term: &str) -> QueryNode {
+        QueryNode::Word(vec![WordTerm {
+            term: term.to_string(),
+            alternatives: Vec::new(),
+            parts: Vec::new(),
+            position: 0,
+        }])
+    }
+
+    fn clauses(node: QueryNode) -> Vec<Clause> {
+        match node {
+            QueryNode::Group(clauses) => clauses,
+            _ => panic!("expected a group, got {:?}", node),
+        }
+    }
+
+    fn error_span(text: &str) -> Range<usize> {
+        match parse_query(text) {
+            Err(Error::QuerySyntaxError { span, .. }) => span,
+            result => panic!("expected a syntax error for {}, got {:?}", text, result),
+        }
+    }
+
+    #[test]
+    fn test_parse_query() -> Result<(), Error> {
+        let clauses = clauses(parse_query(r#"path:src "exact phrase" foo* -bar title~2"#)?);
+        assert_eq!(5, clauses.len());
+
+        assert_eq!(Occur::Should, clauses[0].occur);
+        assert_eq!(Some(Field::Path), clauses[0].field);
+        assert_eq!(word("src"), clauses[0].node);
+        assert_eq!(0..8, clauses[0].span);
+
+        assert_eq!(None, clauses[1].field);
+        assert_eq!(
+            QueryNode::Phrase {
+                terms: vec!["exact".to_string(), "phrase".to_string()],
+                offsets: vec![0, 1],
+                slop: None
+            },
+            clauses[1].node
+        );
+        assert_eq!(9..23, clauses[1].span);
+
+        assert_eq!(QueryNode::Prefix("foo".to_string()), clauses[2].node);
+
+        assert_eq!(Occur::MustNot, clauses[3].occur);
+        assert_eq!(word("bar"), clauses[3].node);
+        assert_eq!(29..33, clauses[3].span);
+
+        assert_eq!(
+            QueryNode::Fuzzy {
+                term: "title".to_string(),
+                distance: 2
+            },
+            clauses[4].node
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_groups_and_slop() -> Result<(), Error> {
+        let clauses = clauses(parse_query(r#"+(a -b) "c d"~3 e~"#)?);
+        assert_eq!(3, clauses.len());
+
+        assert_eq!(Occur::Must, clauses[0].occur);
+        let group = self::clauses(clauses[0].node.clone());
+        assert_eq!(2, group.len());
+        assert_eq!(word("a"), group[0].node);
+        assert_eq!(Occur::MustNot, group[1].occur);
+        assert_eq!(word("b"), group[1].node);
+
+        assert_eq!(
+            QueryNode::Phrase {
+                terms: vec!["c".to_string(), "d".to_string()],
+                offsets: vec![0, 1],
+                slop: Some(3)
+            },
+            clauses[1].node
+        );
+        assert_eq!(
+            QueryNode::Fuzzy {
+                term: "e".to_string(),
+                distance: DEFAULT_FUZZY_DISTANCE
+            },
+            clauses[2].node
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_splits_words_like_documents() -> Result<(), Error> {
+        let clauses = clauses(parse_query("XMLHttpRequest foo.bar !!!")?);
+        assert_eq!(2, clauses.len());
+
+        assert_eq!(
+            QueryNode::Word(vec![WordTerm {
+                term: "XMLHttpRequest".to_string(),
+                alternatives: Vec::new(),
+                parts: vec!["XML".to_string(), "Http".to_string(), "Request".to_string()],
+                position: 0,
+            }]),
+            clauses[0].node
+        );
+        assert_eq!(
+            QueryNode::Word(vec![
+                WordTerm {
+                    term: "foo".to_string(),
+                    alternatives: Vec::new(),
+                    parts: Vec::new(),
+                    position: 0,
+                },
+                WordTerm {
+                    term: "bar".to_string(),
+                    alternatives: Vec::new(),
+                    parts: Vec::new(),
+                    position: 1,
+                },
+            ]),
+            clauses[1].node
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_colons_in_words() -> Result<(), Error> {
+        let clauses = clauses(parse_query("std::io::Read title:foo body:http://x")?);
+        assert_eq!(3, clauses.len());
+
+        let terms = |node: &QueryNode| match node {
+            QueryNode::Word(terms) => terms.iter().map(|t| t.term.clone()).collect::<Vec<_>>(),
+            node => panic!("expected a word, got {:?}", node),
+        };
+        assert_eq!(None, clauses[0].field);
+        assert_eq!(vec!["std", "io", "Read"], terms(&clauses[0].node));
+        assert_eq!(None, clauses[1].field);
+        assert_eq!(vec!["title", "foo"], terms(&clauses[1].node));
+        assert_eq!(Some(Field::Body), clauses[2].field);
+        assert_eq!(vec!["http", "x"], terms(&clauses[2].node));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_with_analyzer() -> Result<(), Error> {
+        let analyzer = Analyzer::default()
+            .with_filter(LowercaseFilter {})
+            .with_filter(SynonymFilter::new().with_group(["car", "auto"]));
+        let clauses = clauses(parse_query_with("Car Foo* \"FooBar baz\"", &analyzer)?);
+        assert_eq!(3, clauses.len());
+
+        assert_eq!(
+            QueryNode::Word(vec![WordTerm {
+                term: "car".to_string(),
+                alternatives: vec!["auto".to_string()],
+                parts: Vec::new(),
+                position: 0,
+            }]),
+            clauses[0].node
+        );
+        assert_eq!(QueryNode::Prefix("foo".to_string()), clauses[1].node);
+        assert_eq!(
+            QueryNode::Phrase {
+                terms: vec!["foobar".to_string(), "baz".to_string()],
+                offsets: vec![0, 1],
+                slop: None
+            },
+            clauses[2].node
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_dropped_words() -> Result<(), Error> {
+        let analyzer = Analyzer::default()
+            .with_filter(LowercaseFilter {})
+            .with_filter(StopwordFilter::new(["the", "foobar"]));
+        let clauses = clauses(parse_query_with(
+            "\"cat in the hat\" baz.FooBar",
+            &analyzer,
+        )?);
+        assert_eq!(2, clauses.len());
+
+        // The terms of a phrase keep their places.
+        assert_eq!(
+            QueryNode::Phrase {
+                terms: vec!["cat".to_string(), "in".to_string(), "hat".to_string()],
+                offsets: vec![0, 1, 3],
+                slop: None
+            },
+            clauses[0].node
+        );
+
+        // The pieces of a word that was removed aren't added to the word before it.
+        assert_eq!(
+            QueryNode::Word(vec![WordTerm {
+                term: "baz".to_string(),
+                alternatives: Vec::new(),
+                parts: Vec::new(),
+                position: 0,
+            }]),
+            clauses[1].node
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_exact() -> Result<(), Error> {
+        let analyzer =
+            Analyzer::default().with_filter(StemFilter::new(Language::English).with_exact());
+        let stemmed = clauses(parse_query_with("indexing =indexing", &analyzer)?);
+        assert_eq!(2, stemmed.len());
+
+        assert_eq!(
+            QueryNode::Word(vec![WordTerm {
+                term: "index".to_string(),
+                alternatives: vec!["=indexing".to_string()],
+                parts: Vec::new(),
+                position: 0,
+            }]),
+            stemmed[0].node
+        );
+        assert_eq!(word("=indexing"), stemmed[1].node);
+
+        // Without exact forms in the index, "=" does nothing.
+        let plain = clauses(parse_query("=indexing")?);
+        assert_eq!(word("indexing"), plain[0].node);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        assert_eq!(4..12, error_span(r#"foo "bar baz"#));
+        assert_eq!(4..5, error_span("foo (bar"));
+        assert_eq!(4..5, error_span("foo ) bar"));
+        assert_eq!(1..2, error_span("- foo"));
+        assert_eq!(1..1, error_span("+"));
+        assert_eq!(3..4, error_span(r#""a"~ b"#));
+        assert_eq!(0..4, error_span(r#""!!""#));
+        assert_eq!(0..1, error_span("*"));
+        assert_eq!(0..5, error_span("-test"));
+        assert_eq!(0..13, error_span("-test -path:a"));
+        assert_eq!(6..10, error_span("+foo (-bar) baz"));
+        // Spans are in bytes.
+        assert_eq!(4..5, error_span("福 )"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("title", "title"));
+        assert_eq!(1, edit_distance("title", "titles"));
+        assert_eq!(1, edit_distance("title", "tittle"));
+        assert_eq!(2, edit_distance("title", "tilte"));
+        assert_eq!(5, edit_distance("", "title"));
+        assert_eq!(1, edit_distance("福", "服"));
+    }
+}
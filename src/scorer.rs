@@ -0,0 +1,441 @@
+use crate::index::{CollectionStats, DocumentData, DocumentId, DocumentTermData, Scorer, TermData};
+
+// The inverse document frequency of a term, which is higher for rarer terms.
+// This is the variant that never goes negative for very common terms.
+fn idf(term_data: &TermData, stats: &CollectionStats) -> f32 {
+    let n = stats.document_count as f32;
+    let df = (term_data.document_count as f32).min(n);
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+}
+
+// How long a document is relative to the average, with b controlling how much it matters.
+fn length_norm(b: f32, length: u64, average_length: f32) -> f32 {
+    if average_length <= 0.0 {
+        return 1.0;
+    }
+    1.0 - b + b * (length as f32 / average_length)
+}
+
+// Okapi BM25, treating the path and body of a document as one piece of text.
+#[derive(Debug, Clone)]
+pub struct Bm25Scorer {
+    pub k1: f32, // how quickly repeated occurrences of a term stop adding to the score
+    pub b: f32,  // how much to penalize longer documents, from 0 to 1
+}
+
+impl Default for Bm25Scorer {
+    fn default() -> Self {
+        Bm25Scorer { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25Scorer {
+    fn score(
+        &self,
+        _doc_id: DocumentId,
+        doc_data: &DocumentData,
+        _terms: &[String],
+        term_data: &[TermData],
+        doc_term_data: &[DocumentTermData],
+        stats: &CollectionStats,
+    ) -> f32 {
+        let length = doc_data.length + doc_data.path_length;
+        let average_length = stats.average_length() + stats.average_path_length();
+        let norm = length_norm(self.b, length, average_length);
+
+        term_data
+            .iter()
+            .zip(doc_term_data.iter())
+            .map(|(term_data, doc_term_data)| {
+                let tf = (doc_term_data.body_count + doc_term_data.path_count) as f32;
+                if tf == 0.0 {
+                    return 0.0;
+                }
+                idf(term_data, stats) * tf * (self.k1 + 1.0) / (tf + self.k1 * norm)
+            })
+            .sum()
+    }
+}
+
+// The weighting for one field of a document in BM25F.
+#[derive(Debug, Clone)]
+pub struct FieldWeight {
+    pub boost: f32, // how much an occurrence in this field counts compared to other fields
+    pub b: f32,     // how much to penalize longer values of this field, from 0 to 1
+}
+
+impl FieldWeight {
+    // The weighted, normalized count of a term in the field. A field without the term
+    // adds nothing, even if the field is empty and its norm is zero.
+    fn tf(&self, count: u64, norm: f32) -> f32 {
+        if count == 0 {
+            return 0.0;
+        }
+        self.boost * count as f32 / norm
+    }
+}
+
+// BM25F, which normalizes and weights the path and body of a document separately
+// before combining them.
+#[derive(Debug, Clone)]
+pub struct Bm25fScorer {
+    pub k1: f32,
+    pub body: FieldWeight,
+    pub path: FieldWeight,
+}
+
+impl Default for Bm25fScorer {
+    fn default() -> Self {
+        Bm25fScorer {
+            k1: 1.2,
+            body: FieldWeight {
+                boost: 1.0,
+                b: 0.75,
+            },
+            path: FieldWeight {
+                boost: 2.0,
+                b: 0.75,
+            },
+        }
+    }
+}
+
+impl Scorer for Bm25fScorer {
+    fn score(
+        &self,
+        _doc_id: DocumentId,
+        doc_data: &DocumentData,
+        _terms: &[String],
+        term_data: &[TermData],
+        doc_term_data: &[DocumentTermData],
+        stats: &CollectionStats,
+    ) -> f32 {
+        let body_norm = length_norm(self.body.b, doc_data.length, stats.average_length());
+        let path_norm = length_norm(
+            self.path.b,
+            doc_data.path_length,
+            stats.average_path_length(),
+        );
+
+        term_data
+            .iter()
+            .zip(doc_term_data.iter())
+            .map(|(term_data, doc_term_data)| {
+                let tf = self.body.tf(doc_term_data.body_count, body_norm)
+                    + self.path.tf(doc_term_data.path_count, path_norm);
+                if tf == 0.0 {
+                    return 0.0;
+                }
+                idf(term_data, stats) * tf * (self.k1 + 1.0) / (tf + self.k1)
+            })
+            .sum()
+    }
+}
+
+// The probability of picking the term at random from all the text in the collection,
+// or None if the term isn't in the collection at all.
+fn collection_probability(term_data: &TermData, stats: &CollectionStats) -> Option<f32> {
+    let length = stats.total_length + stats.total_path_length;
+    if term_data.count == 0 || length == 0 {
+        return None;
+    }
+    Some(term_data.count as f32 / length as f32)
+}
+
+// Query likelihood with Dirichlet prior smoothing, as in Indri.
+// Scores are the log probability of the doc generating the query. Lengths count every
+// token a term can come from, so the probabilities are at most 1 and scores aren't positive.
+#[derive(Debug, Clone)]
+pub struct DirichletScorer {
+    pub mu: f32, // how much weight the collection gets, in words
+}
+
+impl Default for DirichletScorer {
+    fn default() -> Self {
+        DirichletScorer { mu: 2500.0 }
+    }
+}
+
+impl Scorer for DirichletScorer {
+    fn score(
+        &self,
+        _doc_id: DocumentId,
+        doc_data: &DocumentData,
+        _terms: &[String],
+        term_data: &[TermData],
+        doc_term_data: &[DocumentTermData],
+        stats: &CollectionStats,
+    ) -> f32 {
+        let length = (doc_data.length + doc_data.path_length) as f32;
+        term_data
+            .iter()
+            .zip(doc_term_data.iter())
+            .filter_map(|(term_data, doc_term_data)| {
+                // Terms that aren't in the collection lower every doc's score by the same
+                // amount, so they can be skipped.
+                let collection = collection_probability(term_data, stats)?;
+                let tf = (doc_term_data.body_count + doc_term_data.path_count) as f32;
+                Some(((tf + self.mu * collection) / (length + self.mu)).ln())
+            })
+            .sum()
+    }
+}
+
+// Query likelihood with Jelinek-Mercer smoothing, which mixes the doc's and the
+// collection's probabilities for each term.
+#[derive(Debug, Clone)]
+pub struct JelinekMercerScorer {
+    pub lambda: f32, // how much weight the collection gets, clamped to between 0 and 1
+}
+
+impl Default for JelinekMercerScorer {
+    fn default() -> Self {
+        JelinekMercerScorer { lambda: 0.4 }
+    }
+}
+
+impl Scorer for JelinekMercerScorer {
+    fn score(
+        &self,
+        _doc_id: DocumentId,
+        doc_data: &DocumentData,
+        _terms: &[String],
+        term_data: &[TermData],
+        doc_term_data: &[DocumentTermData],
+        stats: &CollectionStats,
+    ) -> f32 {
+        let length = doc_data.length + doc_data.path_length;
+        let lambda = self.lambda.clamp(0.0, 1.0);
+        term_data
+            .iter()
+            .zip(doc_term_data.iter())
+            .filter_map(|(term_data, doc_term_data)| {
+                let collection = collection_probability(term_data, stats)?;
+                let tf = doc_term_data.body_count + doc_term_data.path_count;
+                let document = if length == 0 {
+                    0.0
+                } else {
+                    tf as f32 / length as f32
+                };
+                // With no smoothing, a missing term would make the score -inf, and every
+                // doc missing a term would tie.
+                let probability = (1.0 - lambda) * document + lambda * collection;
+                Some(probability.max(f32::MIN_POSITIVE).ln())
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats() -> CollectionStats {
+        CollectionStats {
+            document_count: 100,
+            total_length: 1000,
+            total_path_length: 300,
+        }
+    }
+
+    fn doc(length: u64, path_length: u64) -> DocumentData {
+        DocumentData {
+            path: "/some/path".to_string(),
+            length,
+            path_length,
+            ..Default::default()
+        }
+    }
+
+    fn term(document_count: u64) -> TermData {
+        TermData {
+            count: document_count * 2,
+            document_count,
+        }
+    }
+
+    fn counts(body_count: u64, path_count: u64) -> DocumentTermData {
+        DocumentTermData {
+            body_count,
+            path_count,
+            ..Default::default()
+        }
+    }
+
+    fn score(
+        scorer: &impl Scorer,
+        doc_data: &DocumentData,
+        term_data: &[TermData],
+        doc_term_data: &[DocumentTermData],
+    ) -> f32 {
+        let terms: Vec<String> = (0..term_data.len()).map(|i| i.to_string()).collect();
+        scorer.score(1, doc_data, &terms, term_data, doc_term_data, &stats())
+    }
+
+    #[test]
+    fn test_bm25_more_occurrences_score_higher() {
+        let scorer = Bm25Scorer::default();
+        let once = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        let twice = score(&scorer, &doc(10, 3), &[term(10)], &[counts(2, 0)]);
+        assert!(once > 0.0);
+        assert!(twice > once);
+    }
+
+    #[test]
+    fn test_bm25_rare_terms_score_higher() {
+        let scorer = Bm25Scorer::default();
+        let rare = score(&scorer, &doc(10, 3), &[term(1)], &[counts(1, 0)]);
+        let common = score(&scorer, &doc(10, 3), &[term(90)], &[counts(1, 0)]);
+        assert!(rare > common);
+        assert!(common > 0.0);
+    }
+
+    #[test]
+    fn test_bm25_shorter_docs_score_higher() {
+        let scorer = Bm25Scorer::default();
+        let short = score(&scorer, &doc(5, 3), &[term(10)], &[counts(1, 0)]);
+        let long = score(&scorer, &doc(50, 3), &[term(10)], &[counts(1, 0)]);
+        assert!(short > long);
+
+        let scorer = Bm25Scorer { k1: 1.2, b: 0.0 };
+        let short = score(&scorer, &doc(5, 3), &[term(10)], &[counts(1, 0)]);
+        let long = score(&scorer, &doc(50, 3), &[term(10)], &[counts(1, 0)]);
+        assert_eq!(short, long);
+    }
+
+    #[test]
+    fn test_bm25_sums_terms() {
+        let scorer = Bm25Scorer::default();
+        let a = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        let b = score(&scorer, &doc(10, 3), &[term(20)], &[counts(3, 0)]);
+        let both = score(
+            &scorer,
+            &doc(10, 3),
+            &[term(10), term(20)],
+            &[counts(1, 0), counts(3, 0)],
+        );
+        assert!((a + b - both).abs() < 1e-5);
+
+        let missing = score(
+            &scorer,
+            &doc(10, 3),
+            &[term(10), term(0)],
+            &[counts(1, 0), counts(0, 0)],
+        );
+        assert_eq!(a, missing);
+    }
+
+    #[test]
+    fn test_bm25f_path_boost() {
+        let scorer = Bm25fScorer::default();
+        let in_path = score(&scorer, &doc(10, 3), &[term(10)], &[counts(0, 1)]);
+        let in_body = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        assert!(in_path > in_body);
+
+        let scorer = Bm25fScorer {
+            path: FieldWeight {
+                boost: 0.0,
+                b: 0.75,
+            },
+            ..Default::default()
+        };
+        let in_path = score(&scorer, &doc(10, 3), &[term(10)], &[counts(0, 1)]);
+        assert_eq!(0.0, in_path);
+    }
+
+    #[test]
+    fn test_bm25f_normalizes_fields_separately() {
+        let scorer = Bm25fScorer::default();
+        // A long path shouldn't penalize a match in the body.
+        let short_path = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        let long_path = score(&scorer, &doc(10, 30), &[term(10)], &[counts(1, 0)]);
+        assert_eq!(short_path, long_path);
+
+        let short_body = score(&scorer, &doc(5, 3), &[term(10)], &[counts(1, 0)]);
+        assert!(short_body > short_path);
+    }
+
+    #[test]
+    fn test_bm25f_empty_field() {
+        // With b = 1, an empty field has a norm of zero.
+        let scorer = Bm25fScorer {
+            path: FieldWeight { boost: 2.0, b: 1.0 },
+            ..Default::default()
+        };
+        let in_body = score(&scorer, &doc(10, 0), &[term(10)], &[counts(1, 0)]);
+        assert!(in_body.is_finite());
+        assert!(in_body > 0.0);
+    }
+
+    #[test]
+    fn test_dirichlet() {
+        let scorer = DirichletScorer::default();
+        let once = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        let twice = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 1)]);
+        let absent = score(&scorer, &doc(10, 3), &[term(10)], &[counts(0, 0)]);
+        assert!(twice > once);
+        assert!(once > absent);
+        assert!(absent.is_finite());
+        assert!(once < 0.0);
+
+        // A longer doc has more chances to contain the term.
+        let long = score(&scorer, &doc(100, 3), &[term(10)], &[counts(1, 0)]);
+        assert!(once > long);
+
+        // Terms that aren't anywhere in the collection don't change the score.
+        let missing = score(
+            &scorer,
+            &doc(10, 3),
+            &[term(10), term(0)],
+            &[counts(1, 0), counts(0, 0)],
+        );
+        assert_eq!(once, missing);
+    }
+
+    #[test]
+    fn test_jelinek_mercer() {
+        let scorer = JelinekMercerScorer::default();
+        let once = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        let twice = score(&scorer, &doc(10, 3), &[term(10)], &[counts(1, 1)]);
+        let absent = score(&scorer, &doc(10, 3), &[term(10)], &[counts(0, 0)]);
+        assert!(twice > once);
+        assert!(once > absent);
+        assert!(absent.is_finite());
+
+        // With no smoothing, the collection only matters for docs without the term.
+        let scorer = JelinekMercerScorer { lambda: 0.0 };
+        let common = score(&scorer, &doc(10, 3), &[term(90)], &[counts(1, 0)]);
+        let rare = score(&scorer, &doc(10, 3), &[term(1)], &[counts(1, 0)]);
+        assert_eq!(common, rare);
+
+        // Docs without a term still get a score, which is lower the fewer terms they have.
+        let absent = score(&scorer, &doc(10, 3), &[term(10)], &[counts(0, 0)]);
+        assert!(absent.is_finite());
+        assert!(common > absent);
+        let both = score(
+            &scorer,
+            &doc(10, 3),
+            &[term(10), term(10)],
+            &[counts(1, 0), counts(0, 0)],
+        );
+        let neither = score(
+            &scorer,
+            &doc(10, 3),
+            &[term(10), term(10)],
+            &[counts(0, 0), counts(0, 0)],
+        );
+        assert!(both > neither);
+
+        // Weights outside of 0 to 1 are clamped.
+        let over = JelinekMercerScorer { lambda: 1.5 };
+        let under = JelinekMercerScorer { lambda: -0.5 };
+        let all = JelinekMercerScorer { lambda: 1.0 };
+        let once = |scorer| score(scorer, &doc(10, 3), &[term(10)], &[counts(1, 0)]);
+        assert_eq!(once(&all), once(&over));
+        assert_eq!(common, once(&under));
+
+        let scorer = JelinekMercerScorer::default();
+        let empty = score(&scorer, &doc(0, 0), &[term(10)], &[counts(0, 0)]);
+        assert!(empty.is_finite());
+    }
+}
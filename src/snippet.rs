@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::index::Match;
+
+// How snippets of a document's text are built around the matches for a search.
+#[derive(Debug, Clone)]
+pub struct SnippetOptions {
+    pub fragment_size: usize, // roughly how many bytes of text are in each fragment
+    pub max_fragments: usize, // the most fragments to put in one snippet
+    pub start: String,        // put before each match
+    pub end: String,          // put after each match
+    pub separator: String,    // put between fragments, and where text is left out at either end
+}
+
+impl Default for SnippetOptions {
+    fn default() -> Self {
+        SnippetOptions {
+            fragment_size: 160,
+            max_fragments: 2,
+            start: "<b>".to_string(),
+            end: "</b>".to_string(),
+            separator: "...".to_string(),
+        }
+    }
+}
+
+// Builds a snippet of text from the fragments with the most distinct matched terms, and then
+// the most matches, with each match wrapped in the start and end markers. The fragments are
+// in the order they appear in the text. If nothing matched, the start of the text is used.
+// Matches that don't fit the text, because it changed since it was indexed, are skipped.
+pub fn snippet(text: &str, matches: &[Match], options: &SnippetOptions) -> String {
+    let mut matches: Vec<(Range<usize>, &str)> = matches
+        .iter()
+        .map(|m| {
+            let start = m.location.offset as usize;
+            (start..start + m.location.length as usize, m.term.as_str())
+        })
+        .filter(|(range, _)| text.get(range.clone()).is_some())
+        .collect();
+    matches.sort_by_key(|(range, _)| (range.start, range.end));
+
+    let mut fragments = choose_fragments(text, &matches, options);
+    if fragments.is_empty() {
+        fragments.push(fragment_range(text, 0..0, options.fragment_size));
+    }
+    fragments.sort_by_key(|range| range.start);
+
+    let mut result = String::new();
+    if !text[..fragments[0].start].trim().is_empty() {
+        result.push_str(&options.separator);
+    }
+    for (i, fragment) in fragments.iter().enumerate() {
+        if i > 0 {
+            result.push_str(&options.separator);
+        }
+        let highlights = matches
+            .iter()
+            .map(|(range, _)| range.clone())
+            .filter(|range| range.start >= fragment.start && range.end <= fragment.end);
+        push_fragment(&mut result, text, fragment.clone(), highlights, options);
+    }
+    if !text[fragments.last().unwrap().end..].trim().is_empty() {
+        result.push_str(&options.separator);
+    }
+    result
+}
+
+// Picks the byte ranges of the best fragments, which don't overlap.
+fn choose_fragments(
+    text: &str,
+    matches: &[(Range<usize>, &str)],
+    options: &SnippetOptions,
+) -> Vec<Range<usize>> {
+    // Score the window of matches that starts with each match.
+    let mut windows = Vec::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut next = 0;
+    for (first, (range, term)) in matches.iter().enumerate() {
+        // The window always contains its first match, even if it's too long to fit.
+        next = next.max(first);
+        while next < matches.len()
+            && (next == first || matches[next].0.end <= range.start + options.fragment_size)
+        {
+            *counts.entry(matches[next].1).or_default() += 1;
+            next += 1;
+        }
+        windows.push(((counts.len(), next - first), first, next));
+        if let Some(count) = counts.get_mut(term) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(term);
+            }
+        }
+    }
+    windows.sort_by(|(a, a_first, _), (b, b_first, _)| b.cmp(a).then(a_first.cmp(b_first)));
+
+    let mut fragments: Vec<Range<usize>> = Vec::new();
+    for (_, first, last) in windows {
+        if fragments.len() >= options.max_fragments {
+            break;
+        }
+        let covered = matches[first].0.start..matches[last - 1].0.end;
+        let fragment = fragment_range(text, covered, options.fragment_size);
+        if fragments
+            .iter()
+            .all(|other| fragment.end <= other.start || other.end <= fragment.start)
+        {
+            fragments.push(fragment);
+        }
+    }
+    fragments
+}
+
+// Returns about size bytes of text centered on covered, without splitting words at either end
+// unless a word is longer than the fragment.
+fn fragment_range(text: &str, covered: Range<usize>, size: usize) -> Range<usize> {
+    let lead = size.saturating_sub(covered.len()) / 2;
+    let mut start = covered.start.saturating_sub(lead);
+    let mut end = (start + size).max(covered.end).min(text.len());
+    // If the fragment runs into the end of the text, use the space at the start instead.
+    start = start.min(end.saturating_sub(size));
+    start = text.floor_char_boundary(start);
+    end = text.ceil_char_boundary(end);
+
+    let splits_word = |i: usize| {
+        let before = text[..i].chars().next_back();
+        let after = text[i..].chars().next();
+        !(before.is_none_or(char::is_whitespace) || after.is_none_or(char::is_whitespace))
+    };
+    if splits_word(start)
+        && let Some(space) = text[start..covered.start].find(char::is_whitespace)
+    {
+        start += space;
+    }
+    if splits_word(end)
+        && let Some(space) = text[covered.end..end].rfind(char::is_whitespace)
+    {
+        end = covered.end + space;
+    }
+    let trimmed = text[start..end].trim_start();
+    start = end - trimmed.len();
+    end = start + trimmed.trim_end().len();
+    start..end
+}
+
+// Appends the text of the fragment, with the highlighted ranges marked and whitespace
+// collapsed so that the snippet is on one line.
+fn push_fragment(
+    result: &mut String,
+    text: &str,
+    fragment: Range<usize>,
+    highlights: impl Iterator<Item = Range<usize>>,
+    options: &SnippetOptions,
+) {
+    let push_text = |result: &mut String, range: Range<usize>| {
+        let mut last_space = false;
+        for c in text[range].chars() {
+            let space = c.is_whitespace();
+            if !(space && last_space) {
+                result.push(if space { ' ' } else { c });
+            }
+            last_space = space;
+        }
+    };
+
+    let mut last = fragment.start;
+    for range in highlights {
+        if range.start < last {
+            // This overlaps a match that's already highlighted.
+            if range.end > last {
+                let end = result.len() - options.end.len();
+                result.truncate(end);
+                push_text(result, last..range.end);
+                result.push_str(&options.end);
+                last = range.end;
+            }
+            continue;
+        }
+        push_text(result, last..range.start);
+        result.push_str(&options.start);
+        push_text(result, range.clone());
+        result.push_str(&options.end);
+        last = range.end;
+    }
+    push_text(result, last..fragment.end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Location;
+
+    // Finds every occurrence of each term in the text.
+    fn find(text: &str, terms: &[&str]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        for term in terms {
+            for (offset, _) in text.match_indices(term) {
+                matches.push(Match {
+                    term: term.to_string(),
+                    location: Location {
+                        line: 0,
+                        column: 0,
+                        offset: offset as u64,
+                        length: term.len() as u64,
+                    },
+                });
+            }
+        }
+        matches
+    }
+
+    fn options(fragment_size: usize, max_fragments: usize) -> SnippetOptions {
+        SnippetOptions {
+            fragment_size,
+            max_fragments,
+            start: "[".to_string(),
+            end: "]".to_string(),
+            separator: "...".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = "apple pie is good. so is apple tart. an apple and a banana make a smoothie, \
+                    and the banana is yellow.";
+        let matches = find(text, &["apple", "banana"]);
+
+        // The window with both terms beats the ones with only one.
+        let result = snippet(text, &matches, &options(30, 1));
+        assert_eq!("...an [apple] and a [banana] make...", result);
+
+        // Then the window with the most matches, in the order they appear.
+        let result = snippet(text, &matches, &options(30, 2));
+        assert_eq!(
+            "[apple] pie is good. so is [apple]...an [apple] and a [banana] make...",
+            result
+        );
+
+        // The whole text fits.
+        let result = snippet(text, &matches, &options(200, 2));
+        assert_eq!(
+            "[apple] pie is good. so is [apple] tart. an [apple] and a [banana] make a smoothie, \
+             and the [banana] is yellow.",
+            result
+        );
+    }
+
+    #[test]
+    fn test_snippet_without_matches() {
+        let text = "the quick brown fox\njumps over the lazy dog";
+        let result = snippet(text, &[], &options(25, 2));
+        assert_eq!("the quick brown fox jumps...", result);
+
+        // Leaving out whitespace doesn't need a separator.
+        let result = snippet("\n  the end\n", &[], &options(25, 2));
+        assert_eq!("the end", result);
+
+        // Matches that don't fit the text are skipped.
+        let result = snippet("short", &find("a longer text", &["text"]), &options(25, 2));
+        assert_eq!("short", result);
+    }
+
+    #[test]
+    fn test_snippet_long_match() {
+        // A match that's longer than a fragment doesn't count toward the windows after it.
+        let text = "apple banana supercalifragilistic fig fig fig";
+        let mut matches = find(text, &["apple", "banana", "supercalifragilistic", "fig"]);
+        matches.sort_by_key(|m| m.location.offset);
+        let result = snippet(text, &matches, &options(15, 1));
+        assert_eq!("[apple] [banana]...", result);
+    }
+
+    #[test]
+    fn test_snippet_overlapping_matches() {
+        let text = "call parseHTTPRequest now";
+        let mut matches = find(text, &["parseHTTPRequest", "HTTP"]);
+        matches.sort_by_key(|m| m.location.offset);
+        let result = snippet(text, &matches, &options(100, 1));
+        assert_eq!("call [parseHTTPRequest] now", result);
+
+        // Multi-byte characters aren't split, and whitespace is collapsed.
+        let text = "ééééé  ééé\t\tcafé ééé ééééé";
+        let result = snippet(text, &find(text, &["café"]), &options(16, 1));
+        assert_eq!("...[café] ééé...", result);
+    }
+}
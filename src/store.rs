@@ -1,26 +1,43 @@
+use std::collections::{HashMap, HashSet};
 use std::io::Cursor;
 
-use crate::index::{DocumentId, DocumentTermData, TermData};
+use crate::index::{
+    CollectionStats, DocumentId, DocumentTermData, IndexSettings, TermData, TermLocations,
+};
 use crate::{error::Error, index::DocumentData};
 use brotopuf::{Deserialize, Serialize};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use fjall::{Keyspace, Partition, PartitionCreateOptions, Slice};
+use fjall::{Batch, Keyspace, Partition, PartitionCreateOptions, Slice};
 
 pub struct IndexStore {
+    keyspace: Keyspace,
     term_store: TermStore,
     document_store: DocumentStore,
     posting_list_store: PostingListStore,
+    document_term_store: DocumentTermStore,
+    path_store: PathStore,
+    stats_store: StatsStore,
+    content_store: ContentStore,
 }
 
 impl IndexStore {
     pub fn new(keyspace: &Keyspace) -> Result<IndexStore, Error> {
-        let term_store = TermStore::with_keyspace(&keyspace)?;
-        let document_store = DocumentStore::with_keyspace(&keyspace)?;
-        let posting_list_store = PostingListStore::with_keyspace(&keyspace)?;
+        let term_store = TermStore::with_keyspace(keyspace)?;
+        let document_store = DocumentStore::with_keyspace(keyspace)?;
+        let posting_list_store = PostingListStore::with_keyspace(keyspace)?;
+        let document_term_store = DocumentTermStore::with_keyspace(keyspace)?;
+        let path_store = PathStore::with_keyspace(keyspace)?;
+        let stats_store = StatsStore::with_keyspace(keyspace)?;
+        let content_store = ContentStore::with_keyspace(keyspace)?;
         Ok(IndexStore {
+            keyspace: keyspace.clone(),
             term_store,
             document_store,
             posting_list_store,
+            document_term_store,
+            path_store,
+            stats_store,
+            content_store,
         })
     }
 
@@ -36,10 +53,35 @@ impl IndexStore {
         &self.posting_list_store
     }
 
+    pub fn document_terms(&self) -> &DocumentTermStore {
+        &self.document_term_store
+    }
+
+    pub fn paths(&self) -> &PathStore {
+        &self.path_store
+    }
+
+    pub fn stats(&self) -> &StatsStore {
+        &self.stats_store
+    }
+
+    pub fn content(&self) -> &ContentStore {
+        &self.content_store
+    }
+
+    // Starts a set of changes that will all be written at once when committed.
+    pub fn batch(&self) -> WriteBatch<'_> {
+        WriteBatch::new(self)
+    }
+
     pub fn delete(self, keyspace: &Keyspace) -> Result<(), Error> {
         keyspace.delete_partition(self.term_store.db)?;
         keyspace.delete_partition(self.document_store.db)?;
         keyspace.delete_partition(self.posting_list_store.db)?;
+        keyspace.delete_partition(self.document_term_store.db)?;
+        keyspace.delete_partition(self.path_store.db)?;
+        keyspace.delete_partition(self.stats_store.db)?;
+        keyspace.delete_partition(self.content_store.db)?;
         Ok(())
     }
 }
@@ -57,7 +99,7 @@ impl TermStore {
 
     pub fn get(&self, term: &str) -> Result<Option<TermData>, Error> {
         match self.db.get(term) {
-            Ok(Some(slice)) => TermData::try_from(slice).map(|d| Some(d)),
+            Ok(Some(slice)) => TermData::try_from(slice).map(Some),
             Ok(None) => Ok(None),
             Err(e) => Err(e.into()),
         }
@@ -66,6 +108,29 @@ impl TermStore {
     pub fn put(&self, term: &str, data: &TermData) -> Result<(), Error> {
         Ok(self.db.insert(term, data)?)
     }
+
+    pub fn remove(&self, term: &str) -> Result<(), Error> {
+        Ok(self.db.remove(term)?)
+    }
+
+    // Adds the counts in data to whatever is already stored for the term.
+    // Callers have to make sure nothing else is changing the term at the same time.
+    pub fn merge(&self, term: &str, data: &TermData) -> Result<TermData, Error> {
+        let mut merged = self.get(term)?.unwrap_or_default();
+        merged.count += data.count;
+        merged.document_count += data.document_count;
+        self.put(term, &merged)?;
+        Ok(merged)
+    }
+
+    // Returns every term that starts with prefix, in sorted order.
+    pub fn prefix(&self, prefix: &str) -> impl Iterator<Item = Result<(String, TermData), Error>> {
+        self.db.prefix(prefix).map(|result| {
+            let (key, data) = result?;
+            let term = str::from_utf8(&key)?.to_string();
+            Ok((term, data.try_into()?))
+        })
+    }
 }
 
 impl TryFrom<Slice> for TermData {
@@ -100,7 +165,7 @@ impl DocumentStore {
 
     pub fn get(&self, id: DocumentId) -> Result<Option<DocumentData>, Error> {
         match self.db.get(id.to_be_bytes()) {
-            Ok(Some(slice)) => DocumentData::try_from(slice).map(|d| Some(d)),
+            Ok(Some(slice)) => DocumentData::try_from(slice).map(Some),
             Ok(None) => Ok(None),
             Err(e) => Err(e.into()),
         }
@@ -110,18 +175,24 @@ impl DocumentStore {
         Ok(self.db.insert(id.to_be_bytes(), data)?)
     }
 
-    pub fn new_id(&self) -> Result<DocumentId, Error> {
-        let mut id = self.db.approximate_len() as DocumentId;
-        loop {
-            match self.get(id)? {
-                Some(_) => {
-                    id += 1;
-                    continue;
-                }
-                None => {
-                    return Ok(id);
-                }
-            }
+    pub fn remove(&self, id: DocumentId) -> Result<(), Error> {
+        Ok(self.db.remove(id.to_be_bytes())?)
+    }
+
+    // Returns every document, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(DocumentId, DocumentData), Error>> {
+        self.db.iter().map(|result| {
+            let (key, data) = result?;
+            let id = (&key[..]).try_get_u128()?;
+            Ok((id, data.try_into()?))
+        })
+    }
+
+    // Returns the highest id of any document, or None if there are no documents.
+    pub fn last_id(&self) -> Result<Option<DocumentId>, Error> {
+        match self.db.last_key_value()? {
+            Some((key, _)) => Ok(Some((&key[..]).try_get_u128()?)),
+            None => Ok(None),
         }
     }
 }
@@ -180,6 +251,23 @@ fn make_posting_list_prefix(term: &str) -> Bytes {
     buf.freeze()
 }
 
+// The first key after every key in the term's posting list.
+fn make_posting_list_end(term: &str) -> Bytes {
+    let mut buf = BytesMut::with_capacity(term.len() * 4 + 1);
+    buf.put(term.as_bytes());
+    buf.put(&[1u8][..]);
+    buf.freeze()
+}
+
+fn parse_posting_list_entry(
+    result: fjall::Result<(Slice, Slice)>,
+) -> Result<(DocumentId, DocumentTermData), Error> {
+    let (key, data) = result?;
+    let (_, doc_id) = parse_posting_list_key(key)?;
+    let data = data.try_into()?;
+    Ok((doc_id, data))
+}
+
 pub struct PostingListStore {
     db: Partition,
 }
@@ -196,14 +284,18 @@ impl PostingListStore {
         term: &str,
     ) -> impl Iterator<Item = Result<(DocumentId, DocumentTermData), Error>> {
         let prefix = make_posting_list_prefix(term);
-        self.db.prefix(prefix).map(|result| match result {
-            Ok((key, data)) => {
-                let (_, doc_id) = parse_posting_list_key(key)?;
-                let data = data.try_into()?;
-                Ok((doc_id, data))
-            }
-            Err(e) => Err(e.into()),
-        })
+        self.db.prefix(prefix).map(parse_posting_list_entry)
+    }
+
+    // Returns the term's posting list, starting at the first document with an id >= start.
+    pub fn get_from(
+        &self,
+        term: &str,
+        start: DocumentId,
+    ) -> impl Iterator<Item = Result<(DocumentId, DocumentTermData), Error>> {
+        let start = make_posting_list_key(term, start);
+        let end = make_posting_list_end(term);
+        self.db.range(start..end).map(parse_posting_list_entry)
     }
 
     pub fn put(
@@ -215,6 +307,25 @@ impl PostingListStore {
         let key = make_posting_list_key(term, document);
         Ok(self.db.insert(&key[..], data)?)
     }
+
+    // Returns the entry for one document in the term's posting list.
+    pub fn get_one(
+        &self,
+        term: &str,
+        document: DocumentId,
+    ) -> Result<Option<DocumentTermData>, Error> {
+        let key = make_posting_list_key(term, document);
+        match self.db.get(&key[..]) {
+            Ok(Some(slice)) => DocumentTermData::try_from(slice).map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn remove(&self, term: &str, document: DocumentId) -> Result<(), Error> {
+        let key = make_posting_list_key(term, document);
+        Ok(self.db.remove(&key[..])?)
+    }
 }
 
 impl TryFrom<Slice> for DocumentTermData {
@@ -236,6 +347,500 @@ impl From<&DocumentTermData> for Slice {
     }
 }
 
+fn make_document_term_key(doc: DocumentId, term: &str) -> Bytes {
+    let mut buf = BytesMut::with_capacity(16 + term.len());
+    buf.put(&doc.to_be_bytes()[..]);
+    buf.put(term.as_bytes());
+    buf.freeze()
+}
+
+// The terms in each document, so that its postings can be found when it's deleted.
+pub struct DocumentTermStore {
+    db: Partition,
+}
+
+impl DocumentTermStore {
+    fn with_keyspace(keyspace: &Keyspace) -> Result<Self, Error> {
+        let options = PartitionCreateOptions::default();
+        let db = keyspace.open_partition("doc_terms", options)?;
+        Ok(DocumentTermStore { db })
+    }
+
+    // Returns every term in the document, in sorted order.
+    pub fn get(&self, document: DocumentId) -> impl Iterator<Item = Result<String, Error>> {
+        self.db.prefix(document.to_be_bytes()).map(|result| {
+            let (key, _) = result?;
+            let term = str::from_utf8(&key[16..])?.to_string();
+            Ok(term)
+        })
+    }
+
+    // Returns where the term is in the body of the document.
+    pub fn get_locations(
+        &self,
+        document: DocumentId,
+        term: &str,
+    ) -> Result<Option<TermLocations>, Error> {
+        let key = make_document_term_key(document, term);
+        match self.db.get(&key[..]) {
+            Ok(Some(slice)) => TermLocations::try_from(slice).map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn put(
+        &self,
+        document: DocumentId,
+        term: &str,
+        locations: &TermLocations,
+    ) -> Result<(), Error> {
+        let key = make_document_term_key(document, term);
+        Ok(self.db.insert(&key[..], locations)?)
+    }
+
+    pub fn remove(&self, document: DocumentId, term: &str) -> Result<(), Error> {
+        let key = make_document_term_key(document, term);
+        Ok(self.db.remove(&key[..])?)
+    }
+}
+
+impl TryFrom<Slice> for TermLocations {
+    type Error = Error;
+
+    fn try_from(value: Slice) -> Result<Self, Error> {
+        let mut buffer = Cursor::new(&value[..]);
+        let mut data = TermLocations::default();
+        data.deserialize(buffer.get_mut())?;
+        Ok(data)
+    }
+}
+
+impl From<&TermLocations> for Slice {
+    fn from(value: &TermLocations) -> Self {
+        let mut buffer = Vec::new();
+        value.serialize(&mut buffer).unwrap();
+        Slice::new(&buffer[..])
+    }
+}
+
+// The id of the document for each path.
+pub struct PathStore {
+    db: Partition,
+}
+
+impl PathStore {
+    fn with_keyspace(keyspace: &Keyspace) -> Result<Self, Error> {
+        let options = PartitionCreateOptions::default();
+        let db = keyspace.open_partition("paths", options)?;
+        Ok(PathStore { db })
+    }
+
+    pub fn get(&self, path: &str) -> Result<Option<DocumentId>, Error> {
+        match self.db.get(path) {
+            Ok(Some(slice)) => Ok(Some((&slice[..]).try_get_u128()?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn put(&self, path: &str, document: DocumentId) -> Result<(), Error> {
+        Ok(self.db.insert(path, document.to_be_bytes())?)
+    }
+
+    pub fn remove(&self, path: &str) -> Result<(), Error> {
+        Ok(self.db.remove(path)?)
+    }
+
+    // Returns every path that starts with prefix, and its document, in sorted order.
+    pub fn prefix(
+        &self,
+        prefix: &str,
+    ) -> impl Iterator<Item = Result<(String, DocumentId), Error>> {
+        self.db.prefix(prefix).map(|result| {
+            let (key, value) = result?;
+            let path = str::from_utf8(&key)?.to_string();
+            Ok((path, (&value[..]).try_get_u128()?))
+        })
+    }
+}
+
+// The original text of each document, compressed. This is only stored if the index is
+// configured to store it.
+pub struct ContentStore {
+    db: Partition,
+}
+
+impl ContentStore {
+    fn with_keyspace(keyspace: &Keyspace) -> Result<Self, Error> {
+        let options = PartitionCreateOptions::default();
+        let db = keyspace.open_partition("content", options)?;
+        Ok(ContentStore { db })
+    }
+
+    pub fn get(&self, document: DocumentId) -> Result<Option<String>, Error> {
+        match self.db.get(document.to_be_bytes()) {
+            Ok(Some(slice)) => decompress_content(&slice).map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn put(&self, document: DocumentId, text: &str) -> Result<(), Error> {
+        Ok(self
+            .db
+            .insert(document.to_be_bytes(), compress_content(text))?)
+    }
+
+    pub fn remove(&self, document: DocumentId) -> Result<(), Error> {
+        Ok(self.db.remove(document.to_be_bytes())?)
+    }
+}
+
+fn compress_content(text: &str) -> Vec<u8> {
+    lz4_flex::compress_prepend_size(text.as_bytes())
+}
+
+fn decompress_content(data: &[u8]) -> Result<String, Error> {
+    let bytes = lz4_flex::decompress_size_prepended(data)?;
+    String::from_utf8(bytes).map_err(|e| e.utf8_error().into())
+}
+
+// The keys of the records in the stats partition.
+const COLLECTION_STATS_KEY: &str = "collection";
+const NEXT_ID_KEY: &str = "next_id";
+const SETTINGS_KEY: &str = "settings";
+
+pub struct StatsStore {
+    db: Partition,
+}
+
+impl StatsStore {
+    fn with_keyspace(keyspace: &Keyspace) -> Result<Self, Error> {
+        let options = PartitionCreateOptions::default();
+        let db = keyspace.open_partition("stats", options)?;
+        Ok(StatsStore { db })
+    }
+
+    // Returns the stats for the whole index, which are all zero for a new index.
+    pub fn get(&self) -> Result<CollectionStats, Error> {
+        match self.db.get(COLLECTION_STATS_KEY) {
+            Ok(Some(slice)) => CollectionStats::try_from(slice),
+            Ok(None) => Ok(CollectionStats::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn put(&self, stats: &CollectionStats) -> Result<(), Error> {
+        Ok(self.db.insert(COLLECTION_STATS_KEY, stats)?)
+    }
+
+    // Returns the id the next new document should get, if one has been saved.
+    pub fn get_next_id(&self) -> Result<Option<DocumentId>, Error> {
+        match self.db.get(NEXT_ID_KEY) {
+            Ok(Some(slice)) => Ok(Some((&slice[..]).try_get_u128()?)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Returns the settings saved with the index, which are the defaults for a new index.
+    pub fn get_settings(&self) -> Result<IndexSettings, Error> {
+        match self.db.get(SETTINGS_KEY) {
+            Ok(Some(slice)) => IndexSettings::try_from(slice),
+            Ok(None) => Ok(IndexSettings::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn put_settings(&self, settings: &IndexSettings) -> Result<(), Error> {
+        Ok(self.db.insert(SETTINGS_KEY, settings)?)
+    }
+}
+
+impl TryFrom<Slice> for IndexSettings {
+    type Error = Error;
+
+    fn try_from(value: Slice) -> Result<Self, Error> {
+        let mut buffer = Cursor::new(&value[..]);
+        let mut settings = IndexSettings::default();
+        settings.deserialize(buffer.get_mut())?;
+        Ok(settings)
+    }
+}
+
+impl From<&IndexSettings> for Slice {
+    fn from(value: &IndexSettings) -> Self {
+        let mut buffer = Vec::new();
+        value.serialize(&mut buffer).unwrap();
+        Slice::new(&buffer[..])
+    }
+}
+
+impl TryFrom<Slice> for CollectionStats {
+    type Error = Error;
+
+    fn try_from(value: Slice) -> Result<Self, Error> {
+        let mut buffer = Cursor::new(&value[..]);
+        let mut stats = CollectionStats::default();
+        stats.deserialize(buffer.get_mut())?;
+        Ok(stats)
+    }
+}
+
+impl From<&CollectionStats> for Slice {
+    fn from(value: &CollectionStats) -> Self {
+        let mut buffer = Vec::new();
+        value.serialize(&mut buffer).unwrap();
+        Slice::new(&buffer[..])
+    }
+}
+
+// Changes to the index that are written all at once by commit, or not at all if the
+// batch is dropped. Term stats, paths, and collection stats are read-modify-write, so the
+// batch keeps the latest value of each and reads see changes made earlier in the batch.
+// Other records are keyed by document id, so they're just written to the batch.
+pub struct WriteBatch<'a> {
+    store: &'a IndexStore,
+    batch: Batch,
+    terms: HashMap<String, TermData>, // a term with a document_count of 0 is removed
+    paths: HashMap<String, Option<DocumentId>>,
+    documents: HashMap<DocumentId, Option<DocumentData>>,
+    claimed_ids: HashSet<DocumentId>,
+    stats: Option<CollectionStats>,
+    next_id: Option<DocumentId>,
+
+    #[cfg(test)]
+    writes_until_failure: Option<usize>,
+}
+
+impl<'a> WriteBatch<'a> {
+    fn new(store: &'a IndexStore) -> Self {
+        WriteBatch {
+            store,
+            batch: store.keyspace.batch(),
+            terms: HashMap::new(),
+            paths: HashMap::new(),
+            documents: HashMap::new(),
+            claimed_ids: HashSet::new(),
+            stats: None,
+            next_id: None,
+            #[cfg(test)]
+            writes_until_failure: None,
+        }
+    }
+
+    // Makes the batch return an error after the given number of writes, so tests can
+    // check what happens when indexing or committing fails partway through.
+    #[cfg(test)]
+    pub(crate) fn fail_after(&mut self, writes: usize) {
+        self.writes_until_failure = Some(writes);
+    }
+
+    pub fn store(&self) -> &IndexStore {
+        self.store
+    }
+
+    fn record_write(&mut self) -> Result<(), Error> {
+        #[cfg(test)]
+        if let Some(writes) = self.writes_until_failure.as_mut() {
+            if *writes == 0 {
+                return Err(std::io::Error::other("injected write failure").into());
+            }
+            *writes -= 1;
+        }
+        Ok(())
+    }
+
+    pub fn get_term(&self, term: &str) -> Result<Option<TermData>, Error> {
+        match self.terms.get(term) {
+            Some(data) if data.document_count == 0 => Ok(None),
+            Some(data) => Ok(Some(data.clone())),
+            None => self.store.terms().get(term),
+        }
+    }
+
+    // Replaces the term's data, removing it if it's no longer in any documents.
+    pub fn put_term(&mut self, term: &str, data: TermData) -> Result<(), Error> {
+        self.record_write()?;
+        self.terms.insert(term.to_string(), data);
+        Ok(())
+    }
+
+    // Adds the counts in data to whatever is already stored for the term.
+    pub fn merge_term(&mut self, term: &str, data: &TermData) -> Result<(), Error> {
+        let mut merged = self.get_term(term)?.unwrap_or_default();
+        merged.count += data.count;
+        merged.document_count += data.document_count;
+        self.put_term(term, merged)
+    }
+
+    pub fn get_path(&self, path: &str) -> Result<Option<DocumentId>, Error> {
+        match self.paths.get(path) {
+            Some(id) => Ok(*id),
+            None => self.store.paths().get(path),
+        }
+    }
+
+    pub fn put_path(&mut self, path: &str, document: DocumentId) -> Result<(), Error> {
+        self.record_write()?;
+        self.paths.insert(path.to_string(), Some(document));
+        Ok(())
+    }
+
+    pub fn remove_path(&mut self, path: &str) -> Result<(), Error> {
+        self.record_write()?;
+        self.paths.insert(path.to_string(), None);
+        Ok(())
+    }
+
+    pub fn get_stats(&self) -> Result<CollectionStats, Error> {
+        match &self.stats {
+            Some(stats) => Ok(stats.clone()),
+            None => self.store.stats().get(),
+        }
+    }
+
+    pub fn put_stats(&mut self, stats: CollectionStats) -> Result<(), Error> {
+        self.record_write()?;
+        self.stats = Some(stats);
+        Ok(())
+    }
+
+    pub fn get_next_id(&self) -> Result<Option<DocumentId>, Error> {
+        match self.next_id {
+            Some(id) => Ok(Some(id)),
+            None => self.store.stats().get_next_id(),
+        }
+    }
+
+    pub fn put_next_id(&mut self, id: DocumentId) -> Result<(), Error> {
+        self.record_write()?;
+        self.next_id = Some(id);
+        Ok(())
+    }
+
+    // Marks an id as given to a new document in this batch. Returns false if it's already
+    // taken, either by a committed document or by another new document in the batch.
+    pub fn claim_id(&mut self, id: DocumentId) -> Result<bool, Error> {
+        if self.claimed_ids.contains(&id) || self.store.documents().get(id)?.is_some() {
+            return Ok(false);
+        }
+        self.claimed_ids.insert(id);
+        Ok(true)
+    }
+
+    pub fn get_document(&self, id: DocumentId) -> Result<Option<DocumentData>, Error> {
+        match self.documents.get(&id) {
+            Some(data) => Ok(data.clone()),
+            None => self.store.documents().get(id),
+        }
+    }
+
+    pub fn put_document(&mut self, id: DocumentId, data: &DocumentData) -> Result<(), Error> {
+        self.record_write()?;
+        let db = &self.store.documents().db;
+        self.batch.insert(db, id.to_be_bytes(), data);
+        self.documents.insert(id, Some(data.clone()));
+        Ok(())
+    }
+
+    pub fn remove_document(&mut self, id: DocumentId) -> Result<(), Error> {
+        self.record_write()?;
+        let db = &self.store.documents().db;
+        self.batch.remove(db, id.to_be_bytes());
+        self.documents.insert(id, None);
+        Ok(())
+    }
+
+    pub fn put_content(&mut self, id: DocumentId, text: &str) -> Result<(), Error> {
+        self.record_write()?;
+        let db = &self.store.content().db;
+        self.batch
+            .insert(db, id.to_be_bytes(), compress_content(text));
+        Ok(())
+    }
+
+    pub fn remove_content(&mut self, id: DocumentId) -> Result<(), Error> {
+        self.record_write()?;
+        let db = &self.store.content().db;
+        self.batch.remove(db, id.to_be_bytes());
+        Ok(())
+    }
+
+    pub fn put_posting(
+        &mut self,
+        term: &str,
+        document: DocumentId,
+        data: &DocumentTermData,
+    ) -> Result<(), Error> {
+        self.record_write()?;
+        let key = make_posting_list_key(term, document);
+        let db = &self.store.posting_lists().db;
+        self.batch.insert(db, &key[..], data);
+        Ok(())
+    }
+
+    pub fn remove_posting(&mut self, term: &str, document: DocumentId) -> Result<(), Error> {
+        self.record_write()?;
+        let key = make_posting_list_key(term, document);
+        let db = &self.store.posting_lists().db;
+        self.batch.remove(db, &key[..]);
+        Ok(())
+    }
+
+    pub fn put_document_term(
+        &mut self,
+        document: DocumentId,
+        term: &str,
+        locations: &TermLocations,
+    ) -> Result<(), Error> {
+        self.record_write()?;
+        let key = make_document_term_key(document, term);
+        let db = &self.store.document_terms().db;
+        self.batch.insert(db, &key[..], locations);
+        Ok(())
+    }
+
+    pub fn remove_document_term(&mut self, document: DocumentId, term: &str) -> Result<(), Error> {
+        self.record_write()?;
+        let key = make_document_term_key(document, term);
+        let db = &self.store.document_terms().db;
+        self.batch.remove(db, &key[..]);
+        Ok(())
+    }
+
+    // Writes every change in the batch atomically.
+    pub fn commit(mut self) -> Result<(), Error> {
+        for (term, data) in std::mem::take(&mut self.terms) {
+            self.record_write()?;
+            let db = &self.store.terms().db;
+            if data.document_count == 0 {
+                self.batch.remove(db, term.as_str());
+            } else {
+                self.batch.insert(db, term.as_str(), &data);
+            }
+        }
+        for (path, id) in std::mem::take(&mut self.paths) {
+            self.record_write()?;
+            let db = &self.store.paths().db;
+            match id {
+                Some(id) => self.batch.insert(db, path.as_str(), id.to_be_bytes()),
+                None => self.batch.remove(db, path.as_str()),
+            }
+        }
+        if let Some(stats) = &self.stats {
+            let db = &self.store.stats().db;
+            self.batch.insert(db, COLLECTION_STATS_KEY, stats);
+        }
+        if let Some(id) = self.next_id {
+            let db = &self.store.stats().db;
+            self.batch.insert(db, NEXT_ID_KEY, id.to_be_bytes());
+        }
+        Ok(self.batch.commit()?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,6 +867,14 @@ mod tests {
         assert_eq!(1, actual.count);
         assert_eq!(2, actual.document_count);
 
+        let merged = store.merge("a", &term_data)?;
+        assert_eq!(2, merged.count);
+        assert_eq!(4, merged.document_count);
+
+        let merged = store.merge("b", &term_data)?;
+        assert_eq!(1, merged.count);
+        assert_eq!(2, store.get("b")?.unwrap().document_count);
+
         Ok(())
     }
 
@@ -278,6 +891,7 @@ mod tests {
         let doc_data = DocumentData {
             path: "/some/path".to_string(),
             length: 3,
+            ..Default::default()
         };
         store.put(123, &doc_data)?;
 
@@ -286,6 +900,10 @@ mod tests {
         assert_eq!("/some/path", actual.path);
         assert_eq!(3, actual.length);
 
+        assert_eq!(Some(123), store.last_id()?);
+        store.put(7, &doc_data)?;
+        assert_eq!(Some(123), store.last_id()?);
+
         Ok(())
     }
 
@@ -302,12 +920,14 @@ mod tests {
         let doc_data = DocumentTermData {
             path_count: 44,
             body_count: 4,
+            ..Default::default()
         };
         store.put("a", 1, &doc_data)?;
 
         let doc_data = DocumentTermData {
             path_count: 55,
             body_count: 5,
+            ..Default::default()
         };
         store.put("a", 2, &doc_data)?;
 
@@ -315,7 +935,7 @@ mod tests {
         let result = result?;
         assert_eq!(2, result.len());
 
-        let (result_doc, result_data) = result.get(0).unwrap();
+        let (result_doc, result_data) = result.first().unwrap();
         assert_eq!(1u128, *result_doc);
         assert_eq!(4, result_data.body_count);
         assert_eq!(44, result_data.path_count);
@@ -325,6 +945,245 @@ mod tests {
         assert_eq!(5, result_data.body_count);
         assert_eq!(55, result_data.path_count);
 
+        assert_eq!(4, store.get_one("a", 1)?.unwrap().body_count);
+        store.remove("a", 1)?;
+        assert!(store.get_one("a", 1)?.is_none());
+        assert_eq!(1, store.get("a").count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_posting_list_store_get_from() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_posting_list_store_get_from")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = PostingListStore::with_keyspace(&keyspace)?;
+
+        for doc in [1, 2, 3] {
+            let doc_data = DocumentTermData {
+                body_count: 2,
+                body_positions: vec![doc as u64, doc as u64 + 10],
+                ..Default::default()
+            };
+            store.put("a", doc, &doc_data)?;
+        }
+        store.put("b", 1, &DocumentTermData::default())?;
+
+        let result: Result<Vec<(DocumentId, DocumentTermData)>, Error> =
+            store.get_from("a", 2).collect();
+        let result = result?;
+        assert_eq!(2, result.len());
+
+        let (result_doc, result_data) = result.first().unwrap();
+        assert_eq!(2u128, *result_doc);
+        assert_eq!(vec![2, 12], result_data.body_positions);
+
+        let (result_doc, result_data) = result.get(1).unwrap();
+        assert_eq!(3u128, *result_doc);
+        assert_eq!(vec![3, 13], result_data.body_positions);
+
+        let result: Result<Vec<(DocumentId, DocumentTermData)>, Error> =
+            store.get_from("a", 4).collect();
+        assert_eq!(0, result?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_store() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_stats_store")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = StatsStore::with_keyspace(&keyspace)?;
+
+        let actual = store.get()?;
+        assert_eq!(0, actual.document_count);
+        assert_eq!(0, actual.total_length);
+
+        let stats = CollectionStats {
+            document_count: 2,
+            total_length: 10,
+            total_path_length: 4,
+        };
+        store.put(&stats)?;
+
+        let actual = store.get()?;
+        assert_eq!(2, actual.document_count);
+        assert_eq!(10, actual.total_length);
+        assert_eq!(4, actual.total_path_length);
+
+        assert_eq!(IndexSettings::default(), store.get_settings()?);
+        let settings = IndexSettings {
+            store_content: true,
+            analyzer: "ParseTokenizer".to_string(),
+        };
+        store.put_settings(&settings)?;
+        assert_eq!(settings, store.get_settings()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_term_store() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_document_term_store")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = DocumentTermStore::with_keyspace(&keyspace)?;
+
+        let locations = TermLocations {
+            offsets: vec![4, 10],
+            lengths: vec![1, 1],
+            lines: vec![0, 1],
+            columns: vec![4, 2],
+        };
+        store.put(1, "b", &locations)?;
+        store.put(1, "a", &TermLocations::default())?;
+        store.put(2, "c", &TermLocations::default())?;
+        store.put(256, "d", &TermLocations::default())?;
+
+        let terms: Result<Vec<String>, Error> = store.get(1).collect();
+        assert_eq!(vec!["a", "b"], terms?);
+
+        store.remove(1, "a")?;
+        let terms: Result<Vec<String>, Error> = store.get(1).collect();
+        assert_eq!(vec!["b"], terms?);
+
+        let terms: Result<Vec<String>, Error> = store.get(3).collect();
+        assert!(terms?.is_empty());
+
+        let found = store.get_locations(1, "b")?.unwrap();
+        assert_eq!(vec![4, 10], found.offsets);
+        assert_eq!(vec![0, 1], found.lines);
+        assert_eq!(vec![4, 2], found.columns);
+        assert!(store.get_locations(1, "a")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_store() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_content_store")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = ContentStore::with_keyspace(&keyspace)?;
+
+        let text = "naïve café ".repeat(100);
+        assert_eq!(None, store.get(1)?);
+        store.put(1, &text)?;
+        assert_eq!(Some(text), store.get(1)?);
+
+        store.remove(1)?;
+        assert_eq!(None, store.get(1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_store() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_path_store")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = PathStore::with_keyspace(&keyspace)?;
+
+        assert_eq!(None, store.get("a/b.txt")?);
+        store.put("a/b.txt", 12)?;
+        assert_eq!(Some(12), store.get("a/b.txt")?);
+        store.put("a/b.txt", 1 << 100)?;
+        assert_eq!(Some(1 << 100), store.get("a/b.txt")?);
+        store.remove("a/b.txt")?;
+        assert_eq!(None, store.get("a/b.txt")?);
+
+        store.put("a/b.txt", 1)?;
+        store.put("a/c.txt", 2)?;
+        store.put("ab.txt", 3)?;
+        let paths: Result<Vec<(String, DocumentId)>, Error> = store.prefix("a/").collect();
+        assert_eq!(
+            vec![("a/b.txt".to_string(), 1), ("a/c.txt".to_string(), 2)],
+            paths?
+        );
+        assert_eq!(3, store.prefix("").count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_batch() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_write_batch")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let store = IndexStore::new(&keyspace)?;
+        store.terms().put(
+            "a",
+            &TermData {
+                count: 1,
+                document_count: 1,
+            },
+        )?;
+
+        let term_data = TermData {
+            count: 2,
+            document_count: 1,
+        };
+        let mut batch = store.batch();
+        batch.merge_term("a", &term_data)?;
+        batch.merge_term("a", &term_data)?;
+        batch.merge_term("b", &term_data)?;
+        batch.put_path("x", 7)?;
+        batch.put_document(7, &DocumentData::default())?;
+        batch.put_next_id(8)?;
+
+        // Reads in the batch see its changes, but the store doesn't until it's committed.
+        assert_eq!(5, batch.get_term("a")?.unwrap().count);
+        assert_eq!(Some(7), batch.get_path("x")?);
+        assert_eq!(1, store.terms().get("a")?.unwrap().count);
+        assert!(store.terms().get("b")?.is_none());
+        assert_eq!(None, store.paths().get("x")?);
+        assert_eq!(Some(8), batch.get_next_id()?);
+        assert_eq!(None, store.stats().get_next_id()?);
+
+        batch.commit()?;
+        assert_eq!(Some(8), store.stats().get_next_id()?);
+        let a = store.terms().get("a")?.unwrap();
+        assert_eq!(5, a.count);
+        assert_eq!(3, a.document_count);
+        assert_eq!(2, store.terms().get("b")?.unwrap().count);
+        assert_eq!(Some(7), store.paths().get("x")?);
+        assert!(store.documents().get(7)?.is_some());
+
+        let mut batch = store.batch();
+        batch.put_term("a", TermData::default())?;
+        batch.remove_path("x")?;
+        assert!(batch.get_term("a")?.is_none());
+        assert_eq!(None, batch.get_path("x")?);
+
+        // Dropping the batch leaves the store alone.
+        drop(batch);
+        assert!(store.terms().get("a")?.is_some());
+
+        let mut batch = store.batch();
+        batch.fail_after(1);
+        batch.remove_path("x")?;
+        assert!(batch.remove_document(7).is_err());
+
         Ok(())
     }
 }
@@ -0,0 +1,378 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebounceEventResult, Debouncer, new_debouncer};
+
+use crate::{
+    crawl::{CrawlEvent, Crawler, FileOutcome, SyncReport, relative_path},
+    error::Error,
+    index::InvertedIndex,
+    writer::DEFAULT_MEMORY_BUDGET,
+};
+
+// Keeps an index up to date with the files under a crawler's root as they change.
+// Changes are collected until nothing has changed for the debounce time, so an editor
+// that writes a temp file and renames it over the original only causes one update.
+pub struct Watcher<'a> {
+    crawler: &'a Crawler,
+    _debouncer: Debouncer<RecommendedWatcher>,
+    events: mpsc::Receiver<DebounceEventResult>,
+}
+
+impl Crawler {
+    // Starts watching the root for changes. Nothing is indexed until wait or run is called.
+    pub fn watch(&self, debounce: Duration) -> Result<Watcher<'_>, Error> {
+        let (sender, events) = mpsc::channel();
+        let mut debouncer = new_debouncer(debounce, sender)?;
+        debouncer
+            .watcher()
+            .watch(self.root(), RecursiveMode::Recursive)?;
+        Ok(Watcher {
+            crawler: self,
+            _debouncer: debouncer,
+            events,
+        })
+    }
+}
+
+impl Watcher<'_> {
+    // Waits up to timeout for files to change, and updates the index for them.
+    // Returns None if nothing changed.
+    pub fn wait(
+        &self,
+        index: &InvertedIndex,
+        timeout: Duration,
+        on_event: impl FnMut(CrawlEvent),
+    ) -> Result<Option<SyncReport>, Error> {
+        match self.events.recv_timeout(timeout) {
+            Ok(result) => {
+                let paths = result?.into_iter().map(|event| event.path);
+                self.apply(index, paths, on_event).map(Some)
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(Error::WatcherStopped {}),
+        }
+    }
+
+    // Updates the index as files change, until the watcher stops or something fails.
+    pub fn run(
+        &self,
+        index: &InvertedIndex,
+        mut on_event: impl FnMut(CrawlEvent),
+    ) -> Result<(), Error> {
+        for result in self.events.iter() {
+            let paths = result?.into_iter().map(|event| event.path);
+            self.apply(index, paths, &mut on_event)?;
+        }
+        Err(Error::WatcherStopped {})
+    }
+
+    // Updates the index for the paths that changed.
+    fn apply(
+        &self,
+        index: &InvertedIndex,
+        paths: impl IntoIterator<Item = PathBuf>,
+        mut on_event: impl FnMut(CrawlEvent),
+    ) -> Result<SyncReport, Error> {
+        let root = self.crawler.root();
+        let mut report = SyncReport::default();
+
+        // Renames show up as changes to both the old and the new path.
+        let changed: BTreeSet<PathBuf> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let relative = path.strip_prefix(root).ok()?;
+                if relative.as_os_str().is_empty() {
+                    return None;
+                }
+                // A changed ignore file can change which files anywhere in its directory
+                // should be indexed.
+                if relative
+                    .file_name()
+                    .is_some_and(|name| name == ".gitignore" || name == ".ignore")
+                {
+                    return Some(root.join(relative.parent()?));
+                }
+                Some(root.join(relative))
+            })
+            .collect();
+
+        // Paths sort right before the paths under them, so anything under another
+        // target can be skipped. Otherwise, it'd be counted twice.
+        let mut targets: Vec<PathBuf> = Vec::new();
+        for path in changed {
+            if !targets.last().is_some_and(|last| path.starts_with(last)) {
+                targets.push(path);
+            }
+        }
+
+        for target in targets.iter() {
+            let relative = match relative_path(root, target) {
+                Ok(relative) => relative,
+                Err(e) => {
+                    let path = target.to_string_lossy().into_owned();
+                    on_event(CrawlEvent::Failed {
+                        path: &path,
+                        error: &e,
+                    });
+                    report.errors.push((path, e));
+                    continue;
+                }
+            };
+            let target_path = self.crawler.document_path(&relative);
+            let under_target = if relative.is_empty() {
+                target_path.clone()
+            } else {
+                format!("{target_path}/")
+            };
+
+            // Index whatever is at the path now. If it's gone, or it's ignored, nothing is.
+            let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+            let mut seen = HashSet::new();
+            match fs::symlink_metadata(target) {
+                Ok(_) => {
+                    for result in self.crawler.walk(Some(target)) {
+                        let (path, result) = match result {
+                            Ok((path, full_path)) => {
+                                let result =
+                                    self.crawler
+                                        .index_file(&mut writer, &path, &full_path, true);
+                                (path, result)
+                            }
+                            Err(e) => (self.crawler.walk_error_path(&e), Err(e)),
+                        };
+                        match result {
+                            Ok(FileOutcome::Added(id)) => {
+                                on_event(CrawlEvent::Indexed { path: &path, id });
+                                report.added += 1;
+                            }
+                            Ok(FileOutcome::Updated(id)) => {
+                                on_event(CrawlEvent::Indexed { path: &path, id });
+                                report.updated += 1;
+                            }
+                            Ok(FileOutcome::Unchanged) => {
+                                on_event(CrawlEvent::Unchanged { path: &path });
+                                report.unchanged += 1;
+                            }
+                            Ok(FileOutcome::Binary) => {
+                                on_event(CrawlEvent::SkippedBinary { path: &path });
+                                report.skipped_binary += 1;
+                                continue;
+                            }
+                            Err(e) => {
+                                on_event(CrawlEvent::Failed {
+                                    path: &path,
+                                    error: &e,
+                                });
+                                report.errors.push((path.clone(), e));
+                            }
+                        }
+                        seen.insert(path);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    let e = Error::from(e);
+                    on_event(CrawlEvent::Failed {
+                        path: &target_path,
+                        error: &e,
+                    });
+                    report.errors.push((target_path, e));
+                    continue;
+                }
+            }
+
+            // Remove documents for the path, or for anything under it, that weren't indexed.
+            let mut vanished = Vec::new();
+            for result in index.paths(&target_path) {
+                let (path, id) = result?;
+                let under = path == target_path || path.starts_with(&under_target);
+                if under && !seen.contains(&path) {
+                    vanished.push((id, path));
+                }
+            }
+            for (id, path) in vanished {
+                // A file that turned binary was already removed.
+                if writer.delete_document(id)? {
+                    on_event(CrawlEvent::Removed { path: &path });
+                    report.removed += 1;
+                }
+            }
+            writer.finish()?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crawl::CrawlOptions, store::IndexStore};
+    use fjall::Config;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::time::Instant;
+
+    fn describe(event: CrawlEvent) -> String {
+        match event {
+            CrawlEvent::Indexed { path, .. } => format!("indexed {path}"),
+            CrawlEvent::Unchanged { path } => format!("unchanged {path}"),
+            CrawlEvent::SkippedBinary { path } => format!("binary {path}"),
+            CrawlEvent::Removed { path } => format!("removed {path}"),
+            CrawlEvent::Failed { path, .. } => format!("failed {path}"),
+        }
+    }
+
+    #[test]
+    fn test_watcher() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_watcher").open().unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let root = Path::new("/tmp/tangerine/test_watcher_tree");
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(root.join("dir"))?;
+        fs::write(root.join(".gitignore"), "ignored/\n")?;
+        fs::write(root.join("kept.txt"), "apple")?;
+        fs::write(root.join("dir/inner.txt"), "banana")?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let crawler = Crawler::new(root, &CrawlOptions::default())?;
+        crawler.sync(&index, |_| {})?;
+        let path = |relative: &str| crawler.document_path(relative);
+
+        // The changes are passed in directly, rather than waiting for notifications.
+        let watcher = crawler.watch(Duration::from_secs(60))?;
+        let apply = |changed: &[&str]| -> Result<Vec<String>, Error> {
+            let paths = changed.iter().map(|path| crawler.root().join(path));
+            let mut events = Vec::new();
+            watcher.apply(&index, paths, |event| events.push(describe(event)))?;
+            Ok(events)
+        };
+
+        // A new file is added.
+        fs::write(root.join("new.txt"), "cherry")?;
+        assert_eq!(
+            vec![format!("indexed {}", path("new.txt"))],
+            apply(&["new.txt"])?
+        );
+        assert!(index.lookup_path(&path("new.txt"))?.is_some());
+
+        // Editors often write a temp file and rename it over the original.
+        let old = index.lookup_path(&path("kept.txt"))?.unwrap();
+        fs::write(root.join(".kept.txt.swp"), "apple pie")?;
+        fs::rename(root.join(".kept.txt.swp"), root.join("kept.txt"))?;
+        assert_eq!(
+            vec![format!("indexed {}", path("kept.txt"))],
+            apply(&[".kept.txt.swp", "kept.txt"])?
+        );
+        assert_ne!(Some(old), index.lookup_path(&path("kept.txt"))?);
+        assert_eq!(None, index.lookup_path(&path(".kept.txt.swp"))?);
+        let pie = index.search(
+            &["pie".to_string()],
+            crate::scorer::Bm25Scorer::default(),
+            10,
+        )?;
+        assert_eq!(1, pie.len());
+        assert_eq!(path("kept.txt"), pie[0].document.path);
+
+        // Renaming a directory moves everything in it.
+        fs::rename(root.join("dir"), root.join("moved"))?;
+        assert_eq!(
+            vec![
+                format!("removed {}", path("dir/inner.txt")),
+                format!("indexed {}", path("moved/inner.txt")),
+            ],
+            apply(&["dir", "dir/inner.txt", "moved", "moved/inner.txt"])?
+        );
+
+        // Ignored files are left alone, and deleted files are removed.
+        fs::create_dir_all(root.join("ignored"))?;
+        fs::write(root.join("ignored/skip.txt"), "date")?;
+        fs::remove_file(root.join("new.txt"))?;
+        assert_eq!(
+            vec![format!("removed {}", path("new.txt"))],
+            apply(&["ignored", "ignored/skip.txt", "new.txt"])?
+        );
+        assert_eq!(None, index.lookup_path(&path("ignored/skip.txt"))?);
+        assert_eq!(2, index.collection_stats()?.document_count);
+
+        // Changing an ignore file updates everything it applies to.
+        fs::write(root.join("moved/.gitignore"), "inner.txt\n")?;
+        assert_eq!(
+            vec![format!("removed {}", path("moved/inner.txt"))],
+            apply(&["moved/.gitignore"])?
+        );
+        fs::write(root.join(".gitignore"), "")?;
+        fs::remove_file(root.join("moved/.gitignore"))?;
+        assert_eq!(
+            vec![
+                format!("indexed {}", path("ignored/skip.txt")),
+                format!("unchanged {}", path("kept.txt")),
+                format!("indexed {}", path("moved/inner.txt")),
+            ],
+            apply(&[".gitignore", "moved/.gitignore"])?
+        );
+        assert_eq!(3, index.collection_stats()?.document_count);
+
+        // A path that isn't valid unicode only fails that path.
+        let invalid = OsStr::from_bytes(b"bad\xff.txt");
+        fs::write(root.join(invalid), "elderberry")?;
+        fs::write(root.join("valid.txt"), "fig")?;
+        let mut events = Vec::new();
+        let report = watcher.apply(
+            &index,
+            [root.join(invalid), root.join("valid.txt")],
+            |event| events.push(describe(event)),
+        )?;
+        assert_eq!(
+            vec![
+                format!("failed {}", path("bad\u{fffd}.txt")),
+                format!("indexed {}", path("valid.txt")),
+            ],
+            events
+        );
+        assert_eq!(1, report.errors.len());
+        assert_eq!(1, report.added);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watcher_notifications() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_watcher_notifications")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let root = Path::new("/tmp/tangerine/test_watcher_notifications_tree");
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(root)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let crawler = Crawler::new(root, &CrawlOptions::default())?;
+        let watcher = crawler.watch(Duration::from_millis(50))?;
+        fs::write(root.join("new.txt"), "apple")?;
+
+        // Wait for the notification, rather than passing the change in.
+        let path = crawler.document_path("new.txt");
+        let mut events = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while index.lookup_path(&path)?.is_none() && Instant::now() < deadline {
+            watcher.wait(&index, Duration::from_secs(1), |event| {
+                events.push(describe(event))
+            })?;
+        }
+        assert_eq!(vec![format!("indexed {path}")], events);
+        assert!(index.lookup_path(&path)?.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,577 @@
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, MutexGuard, mpsc};
+use std::thread;
+
+use crate::{
+    error::Error,
+    index::{DocProcessor, DocumentId, InvertedIndex},
+    store::WriteBatch,
+};
+
+// The default number of bytes of processed documents an IndexWriter holds before flushing.
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// Adds many documents to an index, much faster than add_document.
+// Processed documents are kept in memory until they use up the memory budget, and then
+// written all at once, so each term's stats are only read and written once per flush.
+// Documents aren't searchable until they're flushed, and the writer holds the index's
+// writer lock until it's dropped, so nothing else can change the index in the meantime.
+// Other threads wait for the writer to be dropped, and changes made through the index on
+// the writer's own thread return an error. Documents that haven't been flushed are lost
+// if the writer is dropped without calling finish.
+pub struct IndexWriter<'a> {
+    index: &'a InvertedIndex,
+    _writer: MutexGuard<'a, ()>,
+    batch: WriteBatch<'a>,
+    docs: Vec<DocProcessor>,
+    memory_budget: usize,
+    memory_used: usize,
+}
+
+// How far past the next document to add an ordered add_documents_parallel will tokenize.
+// Documents after that wait, so one slow document can't make the rest pile up in memory.
+const MAX_WAITING: usize = 256;
+
+// How add_documents_parallel splits up the work.
+#[derive(Debug, Clone)]
+pub struct ParallelOptions {
+    pub threads: usize, // the number of threads tokenizing documents
+    pub ordered: bool,  // whether ids are given out in the same order as the documents
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        ParallelOptions {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            ordered: true,
+        }
+    }
+}
+
+impl InvertedIndex {
+    // Returns an error if this thread already has a writer open.
+    pub fn writer(&self, memory_budget: usize) -> Result<IndexWriter<'_>, Error> {
+        Ok(IndexWriter {
+            index: self,
+            _writer: self.open_writer()?,
+            batch: self.batch(),
+            docs: Vec::new(),
+            memory_budget,
+            memory_used: 0,
+        })
+    }
+}
+
+impl Drop for IndexWriter<'_> {
+    fn drop(&mut self) {
+        self.index.close_writer();
+    }
+}
+
+impl<'a> IndexWriter<'a> {
+    // Add a document to the index. The id is chosen right away, even though the document
+    // won't be in the index until the next flush.
+    // If the document is already in the index, this will add it a second time.
+    pub fn add_document(
+        &mut self,
+        path: &str,
+        doc: &mut impl std::io::Read,
+    ) -> Result<DocumentId, Error> {
+        let processor = self.index.process_document(path, doc)?;
+        self.add_processed(processor)
+    }
+
+    // Add a document, replacing any document with the same path when it's flushed,
+    // including one added earlier to this writer.
+    pub fn upsert_document(
+        &mut self,
+        path: &str,
+        doc: &mut impl std::io::Read,
+    ) -> Result<DocumentId, Error> {
+        self.upsert_file(path, doc, 0)
+    }
+
+    // Like upsert_document, but also records when the file was last modified, as in
+    // InvertedIndex::upsert_file.
+    pub fn upsert_file(
+        &mut self,
+        path: &str,
+        doc: &mut impl std::io::Read,
+        modified: u64,
+    ) -> Result<DocumentId, Error> {
+        let mut processor = self.index.process_document(path, doc)?;
+        processor.modified = modified;
+        processor.replace = true;
+        self.add_processed(processor)
+    }
+
+    // Tokenizes documents on several threads, and adds them from this one.
+    // Returns the ids of the documents, in the same order as docs. If options.ordered is
+    // true, the ids are also given out in that order, so indexing the same documents
+    // always gives the same ids, but documents more than MAX_WAITING past a slow one wait
+    // for it. Otherwise, they're given out as documents finish.
+    // If any document fails, the error is returned, but documents that were already
+    // flushed stay in the index.
+    pub fn add_documents_parallel<I, R>(
+        &mut self,
+        docs: I,
+        options: &ParallelOptions,
+    ) -> Result<Vec<DocumentId>, Error>
+    where
+        I: Iterator<Item = (String, R)> + Send,
+        R: std::io::Read,
+    {
+        let index = self.index;
+        let threads = options.threads.max(1);
+        let ordered = options.ordered;
+        let jobs = Mutex::new(docs.enumerate());
+        let (sender, receiver) = mpsc::sync_channel(threads * 4);
+        // The number of documents added so far, or usize::MAX once adding has stopped.
+        let added = &(Mutex::new(0usize), Condvar::new());
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let jobs = &jobs;
+                scope.spawn(move || {
+                    loop {
+                        let next = jobs.lock().unwrap().next();
+                        let Some((i, (path, mut doc))) = next else {
+                            break;
+                        };
+                        if ordered {
+                            let (count, changed) = added;
+                            let mut count = count.lock().unwrap();
+                            while i >= count.saturating_add(MAX_WAITING) {
+                                count = changed.wait(count).unwrap();
+                            }
+                        }
+                        let result = index.process_document(&path, &mut doc);
+                        // The receiver is only gone if adding a document failed.
+                        if sender.send((i, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            // The receiver has to be dropped before the scope waits for the threads,
+            // or any threads still sending would never finish.
+            let result = move || -> Result<Vec<DocumentId>, Error> {
+                let mut ids = Vec::new();
+                let mut waiting = BTreeMap::new();
+                for (i, result) in receiver.iter() {
+                    let processor = result?;
+                    if !ordered {
+                        ids.push((i, self.add_processed(processor)?));
+                        continue;
+                    }
+                    waiting.insert(i, processor);
+                    while let Some(processor) = waiting.remove(&ids.len()) {
+                        ids.push((ids.len(), self.add_processed(processor)?));
+                    }
+                    *added.0.lock().unwrap() = ids.len();
+                    added.1.notify_all();
+                }
+                ids.sort();
+                Ok(ids.into_iter().map(|(_, id)| id).collect())
+            }();
+
+            // Threads waiting for their turn have to stop waiting, too.
+            *added.0.lock().unwrap() = usize::MAX;
+            added.1.notify_all();
+            result
+        })
+    }
+
+    // Removes a document from the index when the writer is next flushed. Returns false if
+    // there was no document with that id. Documents that haven't been flushed yet can't
+    // be removed this way, but upsert_document replaces them.
+    pub fn delete_document(&mut self, id: DocumentId) -> Result<bool, Error> {
+        self.index.remove_document(&mut self.batch, id)
+    }
+
+    // Changes when a document was last modified, as in InvertedIndex::set_modified, when
+    // the writer is next flushed.
+    pub fn set_modified(&mut self, id: DocumentId, modified: u64) -> Result<bool, Error> {
+        let Some(mut doc_data) = self.batch.get_document(id)? else {
+            return Ok(false);
+        };
+        doc_data.modified = modified;
+        self.batch.put_document(id, &doc_data)?;
+        Ok(true)
+    }
+
+    fn add_processed(&mut self, mut processor: DocProcessor) -> Result<DocumentId, Error> {
+        let id = self.index.allocate_id(&mut self.batch, &mut processor)?;
+
+        self.memory_used += processor.memory_size();
+        self.docs.push(processor);
+        if self.memory_used >= self.memory_budget {
+            self.flush()?;
+        }
+        Ok(id)
+    }
+
+    pub fn index(&self) -> &'a InvertedIndex {
+        self.index
+    }
+
+    // The number of documents waiting to be flushed.
+    pub fn pending(&self) -> usize {
+        self.docs.len()
+    }
+
+    // Writes every pending document to the index.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let docs = std::mem::take(&mut self.docs);
+        self.memory_used = 0;
+        // A batch that fails partway is dropped, so a later flush can't commit half of it.
+        let mut batch = std::mem::replace(&mut self.batch, self.index.batch());
+        self.index.write_documents(&mut batch, docs)?;
+        batch.commit()
+    }
+
+    // Writes every pending document to the index, and releases the writer lock.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scorer::Bm25Scorer, store::IndexStore};
+    use fjall::Config;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // The total count of a term and the number of documents it's in, found by searching.
+    fn term_counts(index: &InvertedIndex, term: &str) -> Result<(u64, u64), Error> {
+        let results = index.search(&[term.to_string()], Bm25Scorer::default(), 1000)?;
+        let count = results
+            .iter()
+            .flat_map(|result| result.matched_terms.iter())
+            .map(|matched| matched.body_count + matched.path_count)
+            .sum();
+        Ok((count, results.len() as u64))
+    }
+
+    #[test]
+    fn test_index_writer() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_index_writer")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let first = index.add_document("first.txt", &mut "apple".as_bytes())?;
+
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        let a = writer.add_document("a.txt", &mut "apple banana".as_bytes())?;
+        let b = writer.add_document("b.txt", &mut "banana cherry apple".as_bytes())?;
+        assert_eq!(vec![first + 1, first + 2], vec![a, b]);
+        assert_eq!(2, writer.pending());
+
+        // Nothing is in the index until it's flushed.
+        assert_eq!(None, index.lookup_path("a.txt")?);
+        assert_eq!(1, index.collection_stats()?.document_count);
+
+        writer.flush()?;
+        assert_eq!(0, writer.pending());
+        let c = writer.add_document("c.txt", &mut "cherry".as_bytes())?;
+        writer.finish()?;
+        assert_eq!(first + 3, c);
+
+        assert_eq!(Some(a), index.lookup_path("a.txt")?);
+        assert_eq!(Some(c), index.lookup_path("c.txt")?);
+        let stats = index.collection_stats()?;
+        assert_eq!(4, stats.document_count);
+        assert_eq!(1 + 2 + 3 + 1, stats.total_length);
+
+        let terms: Vec<String> = ["apple", "banana", "cherry"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let actual: Result<Vec<(u64, u64)>, Error> =
+            terms.iter().map(|term| term_counts(&index, term)).collect();
+        assert_eq!(vec![(3, 3), (2, 2), (2, 2)], actual?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer_failed_flush() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_index_writer_failed_flush")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let first = index.add_document("first.txt", &mut "apple".as_bytes())?;
+
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        writer.add_document("a.txt", &mut "apple banana".as_bytes())?;
+        writer.add_document("b.txt", &mut "banana cherry".as_bytes())?;
+        writer.batch.fail_after(1);
+        assert!(writer.flush().is_err());
+
+        // Nothing from the failed flush is committed when the writer finishes.
+        writer.finish()?;
+        assert_eq!(Some(first), index.lookup_path("first.txt")?);
+        assert_eq!(None, index.lookup_path("a.txt")?);
+        assert_eq!(None, index.lookup_path("b.txt")?);
+        assert_eq!(1, index.collection_stats()?.document_count);
+        assert_eq!((1, 1), term_counts(&index, "apple")?);
+        assert_eq!((0, 0), term_counts(&index, "banana")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer_flushes_at_memory_budget() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_index_writer_flushes_at_memory_budget")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let mut writer = index.writer(1)?;
+        writer.add_document("a.txt", &mut "apple".as_bytes())?;
+        assert_eq!(0, writer.pending());
+        writer.add_document("b.txt", &mut "apple".as_bytes())?;
+        assert_eq!(0, writer.pending());
+        drop(writer);
+
+        assert_eq!(2, index.collection_stats()?.document_count);
+        assert_eq!((2, 2), term_counts(&index, "apple")?);
+
+        // Documents that were never flushed aren't added.
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        writer.add_document("c.txt", &mut "apple".as_bytes())?;
+        drop(writer);
+        assert_eq!(2, index.collection_stats()?.document_count);
+        assert_eq!(None, index.lookup_path("c.txt")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer_upsert() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_index_writer_upsert")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let old = index.add_document("a.txt", &mut "apple".as_bytes())?;
+
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        writer.upsert_document("a.txt", &mut "banana".as_bytes())?;
+        writer.add_document("b.txt", &mut "banana".as_bytes())?;
+        let a = writer.upsert_document("a.txt", &mut "cherry".as_bytes())?;
+        let b = writer.upsert_document("b.txt", &mut "cherry".as_bytes())?;
+        writer.finish()?;
+
+        assert!(index.document(old)?.is_none());
+        assert_eq!(Some(a), index.lookup_path("a.txt")?);
+        assert_eq!(Some(b), index.lookup_path("b.txt")?);
+        assert_eq!(2, index.collection_stats()?.document_count);
+        assert_eq!((0, 0), term_counts(&index, "apple")?);
+        assert_eq!((0, 0), term_counts(&index, "banana")?);
+        assert_eq!((2, 2), term_counts(&index, "cherry")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer_same_thread() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_index_writer_same_thread")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let id = index.add_document("a.txt", &mut "apple".as_bytes())?;
+
+        // Changing the index while this thread has a writer open would wait forever.
+        let writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        assert!(matches!(
+            index.add_document("b.txt", &mut "banana".as_bytes()),
+            Err(Error::WriterOpen {})
+        ));
+        assert!(matches!(
+            index.delete_document(id),
+            Err(Error::WriterOpen {})
+        ));
+        assert!(matches!(
+            index.writer(DEFAULT_MEMORY_BUDGET),
+            Err(Error::WriterOpen {})
+        ));
+
+        // Other threads wait for the writer instead.
+        thread::scope(|scope| {
+            let other = scope.spawn(|| index.add_document("c.txt", &mut "cherry".as_bytes()));
+            writer.finish()?;
+            other.join().unwrap()?;
+            Ok::<(), Error>(())
+        })?;
+        index.add_document("d.txt", &mut "date".as_bytes())?;
+        assert_eq!(3, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+
+    fn numbered_docs(count: usize) -> impl Iterator<Item = (String, &'static [u8])> + Send {
+        let words: [&'static [u8]; 3] = [b"apple", b"apple banana", b"banana cherry apple"];
+        (0..count).map(move |i| (format!("{i}.txt"), words[i % 3]))
+    }
+
+    #[test]
+    fn test_add_documents_parallel() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_add_documents_parallel")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let options = ParallelOptions {
+            threads: 4,
+            ordered: true,
+        };
+        let mut writer = index.writer(4096)?;
+        let ids = writer.add_documents_parallel(numbered_docs(100), &options)?;
+        writer.finish()?;
+
+        // Ordered ids match the order of the documents.
+        assert_eq!((0..100).collect::<Vec<DocumentId>>(), ids);
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(Some(*id), index.lookup_path(&format!("{i}.txt"))?);
+        }
+
+        let stats = index.collection_stats()?;
+        assert_eq!(100, stats.document_count);
+        assert_eq!(100, term_counts(&index, "apple")?.1);
+        assert_eq!(33, term_counts(&index, "cherry")?.1);
+
+        // Unordered ids still line up with the documents they were given to.
+        let options = ParallelOptions {
+            threads: 4,
+            ordered: false,
+        };
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        let docs = numbered_docs(20).map(|(path, text)| (format!("more/{path}"), text));
+        let ids = writer.add_documents_parallel(docs, &options)?;
+        writer.finish()?;
+
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!((100..120).collect::<Vec<DocumentId>>(), sorted);
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(Some(*id), index.lookup_path(&format!("more/{i}.txt"))?);
+        }
+
+        Ok(())
+    }
+
+    // Records the highest document index that has started being read. The first document
+    // is slow, and remembers what the highest index was once it's done.
+    struct TrackedReader<'a> {
+        i: usize,
+        started: &'a AtomicUsize,
+        seen: &'a AtomicUsize,
+        text: &'a [u8],
+    }
+
+    impl std::io::Read for TrackedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.started.fetch_max(self.i, Ordering::SeqCst);
+            if self.i == 0 && !self.text.is_empty() {
+                thread::sleep(std::time::Duration::from_millis(200));
+                self.seen
+                    .store(self.started.load(Ordering::SeqCst), Ordering::SeqCst);
+            }
+            self.text.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_add_documents_parallel_limits_waiting() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_add_documents_parallel_limits_waiting")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let started = AtomicUsize::new(0);
+        let seen = AtomicUsize::new(0);
+        let docs = (0..MAX_WAITING * 3).map(|i| {
+            let doc = TrackedReader {
+                i,
+                started: &started,
+                seen: &seen,
+                text: b"apple",
+            };
+            (format!("{i}.txt"), doc)
+        });
+        let options = ParallelOptions {
+            threads: 4,
+            ordered: true,
+        };
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        let ids = writer.add_documents_parallel(docs, &options)?;
+        writer.finish()?;
+
+        // While the first document was being read, nothing past the limit was started.
+        assert!(seen.load(Ordering::SeqCst) < MAX_WAITING);
+        assert_eq!(MAX_WAITING * 3, ids.len());
+        assert_eq!(
+            MAX_WAITING as u64 * 3,
+            index.collection_stats()?.document_count
+        );
+
+        Ok(())
+    }
+
+    struct FailingReader {}
+
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("unreadable"))
+        }
+    }
+
+    #[test]
+    fn test_add_documents_parallel_error() -> Result<(), Error> {
+        let keyspace = Config::new("/tmp/tangerine/test_add_documents_parallel_error")
+            .open()
+            .unwrap();
+
+        IndexStore::new(&keyspace)?.delete(&keyspace)?;
+
+        let index = InvertedIndex::new(&keyspace)?;
+        let docs = (0..50).map(|i| {
+            let doc: Box<dyn std::io::Read> = if i == 10 {
+                Box::new(FailingReader {})
+            } else {
+                Box::new("apple".as_bytes())
+            };
+            (format!("{i}.txt"), doc)
+        });
+        let mut writer = index.writer(DEFAULT_MEMORY_BUDGET)?;
+        let result = writer.add_documents_parallel(docs, &ParallelOptions::default());
+        assert!(result.is_err());
+        drop(writer);
+
+        assert_eq!(0, index.collection_stats()?.document_count);
+
+        Ok(())
+    }
+}